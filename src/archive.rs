@@ -0,0 +1,256 @@
+//! In-process extraction of zip, tar.gz, tar.xz and tar.bz2 packages.
+//!
+//! Executable bits and symlinks are preserved, entries or symlinks escaping the target dir
+//! (zip-slip) are rejected, zip entries are never written through a symlink extracted before,
+//! any error fails the whole extraction.
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Component, Path, PathBuf};
+use rust_util::XResult;
+use flate2::read::GzDecoder;
+use xz2::read::XzDecoder;
+use bzip2::read::BzDecoder;
+
+use crate::misc::VERBOSE;
+
+const S_IFMT: u32 = 0o170000;
+const S_IFLNK: u32 = 0o120000;
+
+/// Extract `file_name` into `dir`, file type is decided by `file_name` suffix
+pub fn extract_package_to(file_name: &str, dir: &str) -> XResult<()> {
+    let file = File::open(file_name)?;
+    let target_dir = Path::new(dir);
+    let entries_count = if file_name.ends_with(".zip") {
+        extract_zip(file, target_dir)?
+    } else if file_name.ends_with(".tar.gz") || file_name.ends_with(".tgz") {
+        extract_tar(GzDecoder::new(file), target_dir)?
+    } else if file_name.ends_with(".tar.xz") || file_name.ends_with(".txz") {
+        extract_tar(XzDecoder::new(file), target_dir)?
+    } else if file_name.ends_with(".tar.bz2") || file_name.ends_with(".tbz2") {
+        extract_tar(BzDecoder::new(file), target_dir)?
+    } else if file_name.ends_with(".tar") {
+        extract_tar(file, target_dir)?
+    } else {
+        return simple_error!("Unknown file type: {}", file_name);
+    };
+    success!("Extract file: {} success, {} entries", file_name, entries_count);
+    Ok(())
+}
+
+fn extract_tar<R: Read>(reader: R, target_dir: &Path) -> XResult<usize> {
+    let mut archive = tar::Archive::new(reader);
+    archive.set_preserve_permissions(true);
+    archive.set_overwrite(true);
+    let mut entries_count = 0_usize;
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let entry_path = entry.path()?.to_path_buf();
+        check_entry_path(&entry_path)?;
+        if entry.header().entry_type().is_symlink() {
+            if let Some(link_target) = entry.link_name()? {
+                check_link_target(&entry_path, &link_target)?;
+            }
+        }
+        if *VERBOSE {
+            debugging!("Extract entry: {}", entry_path.display());
+        }
+        if !entry.unpack_in(target_dir)? {
+            return simple_error!("Unsafe entry in package: {}", entry_path.display());
+        }
+        entries_count += 1;
+    }
+    Ok(entries_count)
+}
+
+fn extract_zip(file: File, target_dir: &Path) -> XResult<usize> {
+    let mut archive = zip::ZipArchive::new(file)?;
+    let canonical_target_dir = fs::canonicalize(target_dir)?;
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i)?;
+        let entry_path = match entry.enclosed_name() {
+            Some(p) => p.to_path_buf(),
+            None => return simple_error!("Unsafe entry in package: {}", entry.name()),
+        };
+        check_entry_path(&entry_path)?;
+        if *VERBOSE {
+            debugging!("Extract entry: {}", entry_path.display());
+        }
+        let target_path = target_dir.join(&entry_path);
+        check_no_symlink_in_path(target_dir, &entry_path, entry.is_dir())?;
+        if entry.is_dir() {
+            fs::create_dir_all(&target_path)?;
+            continue;
+        }
+        if let Some(parent) = target_path.parent() {
+            fs::create_dir_all(parent)?;
+            if !fs::canonicalize(parent)?.starts_with(&canonical_target_dir) {
+                return simple_error!("Unsafe entry in package: {}", entry_path.display());
+            }
+        }
+        let unix_mode = entry.unix_mode();
+        if unix_mode.map(|m| m & S_IFMT == S_IFLNK).unwrap_or(false) {
+            let mut link_target = String::new();
+            entry.read_to_string(&mut link_target)?;
+            check_link_target(&entry_path, Path::new(&link_target))?;
+            create_symlink(&link_target, &target_path)?;
+            continue;
+        }
+        // `exists` follows symlinks, a dangling symlink would be written through
+        if fs::symlink_metadata(&target_path).is_ok() {
+            fs::remove_file(&target_path)?;
+        }
+        let mut target_file = File::create(&target_path)?;
+        io::copy(&mut entry, &mut target_file)?;
+        if let Some(mode) = unix_mode {
+            set_file_mode(&target_path, mode)?;
+        }
+    }
+    Ok(archive.len())
+}
+
+fn check_entry_path(entry_path: &Path) -> XResult<()> {
+    for component in entry_path.components() {
+        match component {
+            Component::Normal(_) | Component::CurDir => (),
+            _ => return simple_error!("Unsafe entry in package: {}", entry_path.display()),
+        }
+    }
+    Ok(())
+}
+
+/// Dirs of `entry_path` in `target_dir` must not be symlinks, e.g. `d/l -> ..` then `d/l/m/evil`,
+/// `entry_path` itself is checked when `include_self`
+fn check_no_symlink_in_path(target_dir: &Path, entry_path: &Path, include_self: bool) -> XResult<()> {
+    let components: Vec<Component> = entry_path.components().collect();
+    let dirs_count = iff!(include_self, components.len(), components.len().saturating_sub(1));
+    let mut path = target_dir.to_path_buf();
+    for component in &components[..dirs_count] {
+        path.push(component);
+        if fs::symlink_metadata(&path).map(|m| m.file_type().is_symlink()).unwrap_or(false) {
+            return simple_error!("Unsafe entry through symlink in package: {}", entry_path.display());
+        }
+    }
+    Ok(())
+}
+
+/// Symlink target must stay inside the target dir, e.g. `a/b -> ../c` is allowed, `a -> ../../c` is not
+fn check_link_target(entry_path: &Path, link_target: &Path) -> XResult<()> {
+    let mut resolved = PathBuf::new();
+    if let Some(parent) = entry_path.parent() {
+        resolved.push(parent);
+    }
+    for component in link_target.components() {
+        match component {
+            Component::Normal(c) => resolved.push(c),
+            Component::CurDir => (),
+            Component::ParentDir => if !resolved.pop() {
+                return simple_error!("Unsafe symlink in package: {} -> {}", entry_path.display(), link_target.display());
+            },
+            Component::RootDir | Component::Prefix(_) => {
+                return simple_error!("Unsafe symlink in package: {} -> {}", entry_path.display(), link_target.display());
+            },
+        }
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn create_symlink(link_target: &str, target_path: &Path) -> io::Result<()> {
+    if fs::symlink_metadata(target_path).is_ok() {
+        fs::remove_file(target_path)?;
+    }
+    std::os::unix::fs::symlink(link_target, target_path)
+}
+
+#[cfg(not(unix))]
+fn create_symlink(link_target: &str, target_path: &Path) -> io::Result<()> {
+    fs::write(target_path, link_target)
+}
+
+#[cfg(unix)]
+fn set_file_mode(target_path: &Path, mode: u32) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(target_path, fs::Permissions::from_mode(mode & 0o7777))
+}
+
+#[cfg(not(unix))]
+fn set_file_mode(_target_path: &Path, _mode: u32) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use zip::write::{FileOptions, ZipWriter};
+    use super::*;
+
+    fn new_test_dir(name: &str) -> PathBuf {
+        let test_dir = std::env::temp_dir().join(format!("buildj-test-archive-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&test_dir);
+        fs::create_dir_all(&test_dir).unwrap();
+        test_dir
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_extract_zip_symlink_chain() {
+        let test_dir = new_test_dir("symlink-chain");
+        let zip_file = test_dir.join("evil.zip");
+        let mut zip_writer = ZipWriter::new(File::create(&zip_file).unwrap());
+        zip_writer.add_symlink("d/l", "..", FileOptions::default()).unwrap();
+        zip_writer.add_symlink("d/l/m", "..", FileOptions::default()).unwrap();
+        zip_writer.start_file("d/l/m/evil", FileOptions::default()).unwrap();
+        zip_writer.write_all(b"evil").unwrap();
+        zip_writer.finish().unwrap();
+
+        let staging_dir = test_dir.join(".staging-evil");
+        fs::create_dir_all(&staging_dir).unwrap();
+        let result = extract_package_to(zip_file.to_str().unwrap(), staging_dir.to_str().unwrap());
+        assert!(result.is_err());
+        assert!(!test_dir.join("evil").exists());
+        assert!(!staging_dir.join("evil").exists());
+        fs::remove_dir_all(&test_dir).unwrap();
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_extract_zip_dangling_symlink() {
+        let test_dir = new_test_dir("dangling-symlink");
+        let zip_file = test_dir.join("evil.zip");
+        let mut zip_writer = ZipWriter::new(File::create(&zip_file).unwrap());
+        zip_writer.add_symlink("a", "b", FileOptions::default()).unwrap();
+        zip_writer.start_file("a", FileOptions::default()).unwrap();
+        zip_writer.write_all(b"a").unwrap();
+        zip_writer.finish().unwrap();
+
+        let staging_dir = test_dir.join(".staging-evil");
+        fs::create_dir_all(&staging_dir).unwrap();
+        extract_package_to(zip_file.to_str().unwrap(), staging_dir.to_str().unwrap()).unwrap();
+        assert!(!staging_dir.join("b").exists());
+        assert_eq!(fs::read_to_string(staging_dir.join("a")).unwrap(), "a");
+        fs::remove_dir_all(&test_dir).unwrap();
+    }
+
+    #[test]
+    fn test_extract_zip() {
+        let test_dir = new_test_dir("zip");
+        let zip_file = test_dir.join("tool.zip");
+        let mut zip_writer = ZipWriter::new(File::create(&zip_file).unwrap());
+        zip_writer.start_file("tool-1.0/bin/tool", FileOptions::default().unix_permissions(0o755)).unwrap();
+        zip_writer.write_all(b"#!/bin/sh").unwrap();
+        zip_writer.finish().unwrap();
+
+        let staging_dir = test_dir.join(".staging-tool");
+        fs::create_dir_all(&staging_dir).unwrap();
+        extract_package_to(zip_file.to_str().unwrap(), staging_dir.to_str().unwrap()).unwrap();
+        assert_eq!(fs::read_to_string(staging_dir.join("tool-1.0/bin/tool")).unwrap(), "#!/bin/sh");
+        fs::remove_dir_all(&test_dir).unwrap();
+    }
+
+    #[test]
+    fn test_check_link_target() {
+        assert!(check_link_target(Path::new("a/b"), Path::new("../c")).is_ok());
+        assert!(check_link_target(Path::new("a"), Path::new("../c")).is_err());
+        assert!(check_link_target(Path::new("a"), Path::new("/etc/passwd")).is_err());
+    }
+}
@@ -1,33 +1,308 @@
-use std::{fs, path::Path};
+use std::{fs, fmt, path::Path, collections::BTreeMap};
+use json::JsonValue;
 use rust_util::XResult;
 
-use crate::http::get_url_content;
 use crate::misc::VERBOSE;
+use crate::registry::{self, RegistryConfig};
+use crate::tool::BuilderName;
+use crate::wrapper::BUILDER_WRAPPER;
 
 pub const BUILD_JSON: &str = "build.json";
 
-const GET_ARCHIVER_VERSION_URL: &str= "https://hatter.ink/repo/archive_info_version.json";
+/// A schema error found in build.json, `path` is the `#` separated JSON path, e.g. `builder#version`
+#[derive(Clone, Debug)]
+pub struct BuildJsonError {
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for BuildJsonError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.path.is_empty() {
+            write!(f, "{}: {}", BUILD_JSON, self.message)
+        } else {
+            write!(f, "{}#{}: {}", BUILD_JSON, self.path, self.message)
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct BuildJsonBuilder {
+    pub name: String,
+    pub version: String,
+}
+
+/// Typed build.json, e.g.
+/// ```json
+/// {
+///     "java": "1.8",
+///     // or "java": { "version": "17", "vendor": ["graalvm", "temurin"] }, vendors in preference order
+///     "builder": { "name": "maven", "version": "3.5.2" },
+///     // or "builder": { "name": "wrapper" }, use the version of mvnw or gradlew
+///     "envs": [["VAR_NAME", "VAR_VALUE"]],
+///     "xArgs": { "build": ["clean", "install"] },
+///     "xRuns": { "pub": ["./publish"] },
+///     "registries": ["https://mirror.example.com"],
+///     "toolchains": true // run maven with `--global-toolchains`, gradle with `org.gradle.java.installations.paths`
+/// }
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct BuildJson {
+    /// Java version or version range
+    pub java: Option<String>,
+    /// Acceptable Java vendors in preference order, empty means any vendor
+    pub java_vendors: Vec<String>,
+    pub builder: Option<BuildJsonBuilder>,
+    pub envs: Vec<(String, String)>,
+    pub x_args: BTreeMap<String, Vec<String>>,
+    pub x_runs: BTreeMap<String, Vec<String>>,
+    pub registries: Vec<RegistryConfig>,
+    /// Pass buildj JDKs to maven/gradle toolchains, default off
+    pub toolchains: Option<bool>,
+    /// Dir of build.json or the inferred project, not read from build.json
+    pub project_dir: String,
+}
+
+impl BuildJson {
+    /// Parse and validate build.json, all schema errors are collected and returned together
+    pub fn parse(build_json_object: &JsonValue) -> Result<BuildJson, Vec<BuildJsonError>> {
+        let mut parser = BuildJsonParser::default();
+        let build_json = parser.parse(build_json_object);
+        iff!(parser.errors.is_empty(), Ok(build_json), Err(parser.errors))
+    }
+
+    /// Java request recorded in `build.lock.json`, e.g. `17`, `17@graalvm,temurin`
+    pub fn get_java_requested(&self) -> Option<String> {
+        let java = self.java.as_ref()?;
+        Some(iff!(self.java_vendors.is_empty(), java.clone(), format!("{}@{}", java, self.java_vendors.join(","))))
+    }
+}
+
+#[derive(Default)]
+struct BuildJsonParser {
+    errors: Vec<BuildJsonError>,
+}
+
+impl BuildJsonParser {
+    fn error(&mut self, path: &str, message: String) {
+        self.errors.push(BuildJsonError { path: path.to_string(), message });
+    }
+
+    fn type_error(&mut self, path: &str, expected: &str, value: &JsonValue) {
+        let mut message = format!("expected {}, found {}", expected, get_json_type_name(value));
+        if expected == "string" && value.is_number() {
+            message.push_str(&format!(", quote it: \"{}\"", value));
+        }
+        self.error(path, message);
+    }
+
+    fn parse(&mut self, build_json_object: &JsonValue) -> BuildJson {
+        let mut build_json = BuildJson::default();
+        if !build_json_object.is_object() {
+            self.type_error("", "object", build_json_object);
+            return build_json;
+        }
+        for (key, value) in build_json_object.entries() {
+            match key {
+                "java" => if value.is_object() {
+                    if let Some((java, java_vendors)) = self.parse_java(key, value) {
+                        build_json.java = Some(java);
+                        build_json.java_vendors = java_vendors;
+                    }
+                } else {
+                    build_json.java = self.parse_string(key, value);
+                },
+                "builder" => build_json.builder = self.parse_builder(key, value),
+                "envs" => build_json.envs = self.parse_envs(key, value),
+                "xArgs" => build_json.x_args = self.parse_string_array_map(key, value, false),
+                "xRuns" => build_json.x_runs = self.parse_string_array_map(key, value, true),
+                "registries" => build_json.registries = self.parse_registries(key, value),
+                "toolchains" => build_json.toolchains = self.parse_bool(key, value),
+                // consumed by build.js, not by buildj
+                "repo" => (),
+                _ => self.error(key, "unknown key".to_string()),
+            }
+        }
+        build_json
+    }
+
+    fn parse_string(&mut self, path: &str, value: &JsonValue) -> Option<String> {
+        match value.as_str() {
+            Some(s) => Some(s.to_string()),
+            None => {
+                self.type_error(path, "string", value);
+                None
+            },
+        }
+    }
+
+    fn parse_bool(&mut self, path: &str, value: &JsonValue) -> Option<bool> {
+        match value.as_bool() {
+            Some(b) => Some(b),
+            None => {
+                self.type_error(path, "boolean", value);
+                None
+            },
+        }
+    }
+
+    /// `{ "version": "17", "vendor": "temurin" }`, vendor is a string or an array of strings
+    fn parse_java(&mut self, path: &str, value: &JsonValue) -> Option<(String, Vec<String>)> {
+        let mut version = None;
+        let mut vendors = vec![];
+        for (key, sub_value) in value.entries() {
+            let sub_path = format!("{}#{}", path, key);
+            match key {
+                "version" => version = self.parse_string(&sub_path, sub_value),
+                "vendor" => vendors = self.parse_java_vendors(&sub_path, sub_value),
+                _ => self.error(&sub_path, "unknown key".to_string()),
+            }
+        }
+        if !value.has_key("version") {
+            self.error(&format!("{}#version", path), "java version is not assigned".to_string());
+        }
+        Some((version?, vendors))
+    }
+
+    fn parse_java_vendors(&mut self, path: &str, value: &JsonValue) -> Vec<String> {
+        let mut vendors = vec![];
+        if value.is_string() {
+            vendors.extend(self.parse_string(path, value));
+        } else if value.is_array() {
+            for (i, vendor) in value.members().enumerate() {
+                vendors.extend(self.parse_string(&format!("{}#{}", path, i), vendor));
+            }
+        } else {
+            self.type_error(path, "string or array", value);
+            return vendors;
+        }
+        if vendors.iter().any(|vendor| vendor.trim().is_empty()) {
+            self.error(path, "java vendor is empty".to_string());
+        }
+        vendors
+    }
+
+    fn parse_builder(&mut self, path: &str, value: &JsonValue) -> Option<BuildJsonBuilder> {
+        if !value.is_object() {
+            self.type_error(path, "object", value);
+            return None;
+        }
+        let mut name = None;
+        let mut version = None;
+        for (key, sub_value) in value.entries() {
+            let sub_path = format!("{}#{}", path, key);
+            match key {
+                "name" => name = self.parse_string(&sub_path, sub_value),
+                "version" => version = self.parse_string(&sub_path, sub_value),
+                _ => self.error(&sub_path, "unknown key".to_string()),
+            }
+        }
+        if !value.has_key("name") {
+            self.error(&format!("{}#name", path), "builder name is not assigned".to_string());
+        }
+        let is_wrapper = name.as_deref() == Some(BUILDER_WRAPPER);
+        if is_wrapper {
+            // version comes from the wrapper properties
+            return Some(BuildJsonBuilder { name: name?, version: version.unwrap_or_default() });
+        }
+        if !value.has_key("version") {
+            self.error(&format!("{}#version", path), "builder version is not assigned".to_string());
+        }
+        if let Some(n) = &name {
+            if BuilderName::parse(n).is_none() {
+                self.error(&format!("{}#name", path), format!("unknown builder: {}, supported: {}, {}", n, BuilderName::supported_names(), BUILDER_WRAPPER));
+            }
+        }
+        Some(BuildJsonBuilder { name: name?, version: version? })
+    }
+
+    fn parse_registries(&mut self, path: &str, value: &JsonValue) -> Vec<RegistryConfig> {
+        let mut registries = vec![];
+        if !value.is_array() {
+            self.type_error(path, "array", value);
+            return registries;
+        }
+        for (i, registry_object) in value.members().enumerate() {
+            match RegistryConfig::parse(registry_object) {
+                Ok(registry_config) => registries.push(registry_config),
+                Err(err) => self.error(&format!("{}#{}", path, i), err.to_string()),
+            }
+        }
+        registries
+    }
+
+    fn parse_envs(&mut self, path: &str, value: &JsonValue) -> Vec<(String, String)> {
+        let mut envs = vec![];
+        if !value.is_array() {
+            self.type_error(path, "array", value);
+            return envs;
+        }
+        for (i, env) in value.members().enumerate() {
+            let env_path = format!("{}#{}", path, i);
+            if !env.is_array() || env.len() != 2 {
+                self.error(&env_path, format!("expected [\"NAME\", \"VALUE\"], found {}", env));
+                continue;
+            }
+            let env_k = self.parse_string(&format!("{}#0", env_path), &env[0]);
+            let env_v = self.parse_string(&format!("{}#1", env_path), &env[1]);
+            if let (Some(k), Some(v)) = (env_k, env_v) {
+                envs.push((k, v));
+            }
+        }
+        envs
+    }
+
+    fn parse_string_array_map(&mut self, path: &str, value: &JsonValue, require_non_empty: bool) -> BTreeMap<String, Vec<String>> {
+        let mut map = BTreeMap::new();
+        if !value.is_object() {
+            self.type_error(path, "object", value);
+            return map;
+        }
+        for (key, sub_value) in value.entries() {
+            let sub_path = format!("{}#{}", path, key);
+            if !sub_value.is_array() {
+                self.type_error(&sub_path, "array", sub_value);
+                continue;
+            }
+            if require_non_empty && sub_value.is_empty() {
+                self.error(&sub_path, "command is not assigned".to_string());
+                continue;
+            }
+            let mut items = vec![];
+            for (i, item) in sub_value.members().enumerate() {
+                if let Some(s) = self.parse_string(&format!("{}#{}", sub_path, i), item) {
+                    items.push(s);
+                }
+            }
+            map.insert(key.to_string(), items);
+        }
+        map
+    }
+}
+
+fn get_json_type_name(value: &JsonValue) -> &'static str {
+    match value {
+        JsonValue::Null => "null",
+        JsonValue::Short(_) | JsonValue::String(_) => "string",
+        JsonValue::Number(_) => "number",
+        JsonValue::Boolean(_) => "boolean",
+        JsonValue::Object(_) => "object",
+        JsonValue::Array(_) => "array",
+    }
+}
 
 pub fn get_archive_version(gid: &str, aid: &str) -> XResult<String> {
     if *VERBOSE {
         debugging!("Start get archive info: {}:{}", gid, aid);
     }
-    let mut url = String::with_capacity(1024);
-    url.push_str(GET_ARCHIVER_VERSION_URL);
-    url.push_str("?gid=");
-    url.push_str(&urlencoding::encode(gid));
-    url.push_str("&aid=");
-    url.push_str(&urlencoding::encode(aid));
-    let version_result = get_url_content(url.as_str())?;
-    if *VERBOSE {
-        debugging!("Get archive result: {}", version_result);
-    }
-    let version_result_object = json::parse(&version_result)?;
-    if version_result_object["status"] != 200 {
-        simple_error!("Get archive info version failed: {}", version_result)
-    } else {
-        Ok(version_result_object["data"].to_string())
+    let registries = registry::get_registries();
+    for registry in &registries {
+        match registry.get_archive_version(gid, aid) {
+            Ok(version) => return Ok(version),
+            Err(err) => warning!("Get archive info {}:{} from registry {} failed: {}", gid, aid, registry.description(), err),
+        }
     }
+    simple_error!("Get archive info {}:{} failed, tried {} registries", gid, aid, registries.len())
 }
 
 pub fn create_build_json(args: &[String]) {
@@ -42,12 +317,14 @@ pub fn create_build_json(args: &[String]) {
     for arg in args {
         if arg.starts_with("--java") && arg.len() > 6 {
             java_version = &arg.as_str()[6..];
-        } else if arg.starts_with("--maven") && arg.len() > 7 {
-            builder = "maven";
-            builder_version = &arg.as_str()[7..];
-        } else if arg.starts_with("--gradle") && arg.len() > 8 {
-            builder = "gradle";
-            builder_version = &arg.as_str()[8..];
+        } else {
+            for builder_name in BuilderName::all() {
+                let builder_arg = format!("--{}", builder_name.name());
+                if arg.starts_with(&builder_arg) && arg.len() > builder_arg.len() {
+                    builder = builder_name.name();
+                    builder_version = &arg.as_str()[builder_arg.len()..];
+                }
+            }
         }
     }
     if java_version.is_empty() || builder.is_empty() || builder_version.is_empty() {
@@ -114,9 +391,94 @@ pub fn find_build_json() -> Option<String> {
             warning!("Cannot find {} in current dir, find: {}", BUILD_JSON, p);
             Some(p)
         },
-        None => {
-            failure!("Cannot find {}", BUILD_JSON);
-            None
-        },
+        None => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_errors(build_json: &str) -> Vec<String> {
+        match BuildJson::parse(&json::parse(build_json).unwrap()) {
+            Ok(build_json) => panic!("build.json is valid: {:?}", build_json),
+            Err(errs) => errs.iter().map(|err| err.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_parse_full() {
+        let build_json = BuildJson::parse(&json::parse(r#"{
+            "java": { "version": "17", "vendor": ["graalvm", "temurin"] },
+            "builder": { "name": "maven", "version": "3.5.2" },
+            "envs": [["VAR_NAME", "VAR_VALUE"]],
+            "xArgs": { "build": ["clean", "install"] },
+            "xRuns": { "pub": ["./publish", "--dry-run"] },
+            "registries": ["https://mirror.example.com", { "url": "https://mirror2.example.com", "auth_token": "token" }],
+            "toolchains": true,
+            "repo": { "dependencies": [] }
+        }"#).unwrap()).unwrap();
+        assert_eq!(build_json.java.as_deref(), Some("17"));
+        assert_eq!(build_json.java_vendors, vec!["graalvm", "temurin"]);
+        assert_eq!(build_json.get_java_requested().as_deref(), Some("17@graalvm,temurin"));
+        let builder = build_json.builder.unwrap();
+        assert_eq!((builder.name.as_str(), builder.version.as_str()), ("maven", "3.5.2"));
+        assert_eq!(build_json.envs, vec![("VAR_NAME".to_string(), "VAR_VALUE".to_string())]);
+        assert_eq!(build_json.x_args["build"], vec!["clean", "install"]);
+        assert_eq!(build_json.x_runs["pub"], vec!["./publish", "--dry-run"]);
+        assert_eq!(build_json.registries.len(), 2);
+        assert_eq!(build_json.toolchains, Some(true));
+    }
+
+    #[test]
+    fn test_parse_java_string_and_wrapper() {
+        let build_json = BuildJson::parse(&json::parse(r#"{"java": "1.8", "builder": {"name": "wrapper"}}"#).unwrap()).unwrap();
+        assert_eq!(build_json.java.as_deref(), Some("1.8"));
+        assert!(build_json.java_vendors.is_empty());
+        assert_eq!(build_json.get_java_requested().as_deref(), Some("1.8"));
+        assert_eq!(build_json.builder.unwrap().name, BUILDER_WRAPPER);
+    }
+
+    #[test]
+    fn test_parse_wrong_type() {
+        assert_eq!(parse_errors(r#"{"java": 17}"#), vec![r#"build.json#java: expected string, found number, quote it: "17""#]);
+        assert_eq!(parse_errors(r#"{"java": {"version": "17", "vendor": 1}}"#),
+                   vec!["build.json#java#vendor: expected string or array, found number"]);
+        assert_eq!(parse_errors(r#"{"builder": "maven"}"#), vec!["build.json#builder: expected object, found string"]);
+        assert_eq!(parse_errors(r#"{"builder": {"name": "maven", "version": 3}}"#),
+                   vec![r#"build.json#builder#version: expected string, found number, quote it: "3""#]);
+        assert_eq!(parse_errors(r#"{"xArgs": ["clean"]}"#), vec!["build.json#xArgs: expected object, found array"]);
+        assert_eq!(parse_errors(r#"{"xArgs": {"build": "clean"}}"#), vec!["build.json#xArgs#build: expected array, found string"]);
+        assert_eq!(parse_errors(r#"{"xArgs": {"build": ["clean", null]}}"#), vec!["build.json#xArgs#build#1: expected string, found null"]);
+        assert_eq!(parse_errors(r#"{"toolchains": "true"}"#), vec!["build.json#toolchains: expected boolean, found string"]);
+        assert_eq!(parse_errors("[]"), vec!["build.json: expected object, found array"]);
+    }
+
+    #[test]
+    fn test_parse_unknown_key() {
+        assert_eq!(parse_errors(r#"{"jave": "17"}"#), vec!["build.json#jave: unknown key"]);
+        assert_eq!(parse_errors(r#"{"builder": {"name": "maven", "version": "3.5.2", "home": "/opt"}}"#),
+                   vec!["build.json#builder#home: unknown key"]);
+        assert_eq!(parse_errors(r#"{"builder": {"name": "make", "version": "4.3"}}"#),
+                   vec![format!("build.json#builder#name: unknown builder: make, supported: {}, wrapper", BuilderName::supported_names())]);
+    }
+
+    #[test]
+    fn test_parse_errors_path() {
+        // all errors are collected, with the JSON path of the value
+        let errs = parse_errors(r#"{
+            "java": {"vendor": ["temurin", ""]},
+            "builder": {"name": "gradle"},
+            "envs": [["A", "1"], ["B"], ["C", 3]],
+            "xRuns": {"pub": []}
+        }"#);
+        assert_eq!(errs, vec![
+            "build.json#java#vendor: java vendor is empty",
+            "build.json#java#version: java version is not assigned",
+            "build.json#builder#version: builder version is not assigned",
+            r#"build.json#envs#1: expected ["NAME", "VALUE"], found ["B"]"#,
+            r#"build.json#envs#2#1: expected string, found number, quote it: "3""#,
+            "build.json#xRuns#pub: command is not assigned",
+        ]);
     }
 }
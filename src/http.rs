@@ -1,14 +1,189 @@
-use std::fs::File;
+use std::{env, fmt, fs, thread};
+use std::error::Error;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::time::Duration;
+use reqwest::{StatusCode, blocking::Client, header::{CONTENT_LENGTH, RANGE}};
 use rust_util::{XResult, util_io};
 
+use crate::local_util::IntegrityVerifier;
 use crate::misc::VERBOSE;
 
-pub fn download_url(url: &str, dest: &mut File) -> XResult<()> {
+const PART_FILE_SUFFIX: &str = ".part";
+
+lazy_static! {
+    pub static ref CONNECT_TIMEOUT_SECS: u64 = get_env_u64("BUILDJ_CONNECT_TIMEOUT", 30);
+    pub static ref READ_TIMEOUT_SECS: u64 = get_env_u64("BUILDJ_READ_TIMEOUT", 60);
+    pub static ref DOWNLOAD_RETRIES: u32 = get_env_u64("BUILDJ_DOWNLOAD_RETRIES", 3) as u32;
+}
+
+fn get_env_u64(key: &str, default_value: u64) -> u64 {
+    env::var(key).ok().and_then(|v| v.parse().ok()).unwrap_or(default_value)
+}
+
+#[derive(Debug)]
+struct NotRetryableError(String);
+
+impl fmt::Display for NotRetryableError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Error for NotRetryableError {}
+
+/// HTTP 404 or missing `file://` file, never retried
+#[derive(Debug)]
+pub struct NotFoundError(String);
+
+impl fmt::Display for NotFoundError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Error for NotFoundError {}
+
+/// Feeds written bytes to the integrity digest, fails when more than `max_size` bytes are written
+struct VerifyWriter<'a, W: Write> {
+    inner: W,
+    integrity_verifier: Option<&'a mut IntegrityVerifier>,
+    written: u64,
+    max_size: Option<u64>,
+}
+
+impl<'a, W: Write> Write for VerifyWriter<'a, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if let Some(max_size) = self.max_size {
+            if self.written + buf.len() as u64 > max_size {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, format!("Download size exceeds advertised size: {} bytes", max_size)));
+            }
+        }
+        let len = self.inner.write(buf)?;
+        if let Some(integrity_verifier) = &mut self.integrity_verifier {
+            integrity_verifier.input(&buf[..len]);
+        }
+        self.written += len as u64;
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+fn build_client() -> XResult<Client> {
+    Ok(Client::builder()
+        .connect_timeout(Duration::from_secs(*CONNECT_TIMEOUT_SECS))
+        // blocking client applies this timeout to each read
+        .timeout(Duration::from_secs(*READ_TIMEOUT_SECS))
+        .build()?)
+}
+
+/// Download into `<dest_file_name>.part`, retry with exponential backoff and resume by HTTP Range,
+/// `integrity` is verified while streaming, download is aborted when `expected_size` is exceeded,
+/// rename to `dest_file_name` when finished and verified, the part file is deleted when verify failed
+pub fn download_url(url: &str, dest_file_name: &str, integrity: Option<&str>, expected_size: Option<u64>) -> XResult<()> {
     if *VERBOSE {
         debugging!("Start download URL: {}", url);
     }
-    let mut response = reqwest::blocking::get(url)?;
-    let header_content_length: i64 = match response.headers().get("content-length") {
+    let part_file_name = format!("{}{}", dest_file_name, PART_FILE_SUFFIX);
+    let client = build_client()?;
+    let mut retry = 0_u32;
+    loop {
+        let mut integrity_verifier = match integrity {
+            Some(integrity) => Some(IntegrityVerifier::new(integrity)?),
+            None => None,
+        };
+        let download_result = if let Some(path) = url.strip_prefix("file://") {
+            copy_file_to_part(path, &part_file_name, integrity_verifier.as_mut(), expected_size)
+        } else {
+            download_url_to_part(&client, url, &part_file_name, integrity_verifier.as_mut(), expected_size)
+        };
+        match download_result {
+            Ok(written) => {
+                return finish_part_file(&part_file_name, dest_file_name, integrity_verifier.as_mut(), written, expected_size);
+            },
+            Err(err) => {
+                if retry >= *DOWNLOAD_RETRIES || err.is::<NotRetryableError>() || err.is::<NotFoundError>() {
+                    return Err(err);
+                }
+                retry += 1;
+                let backoff_secs = 1_u64 << retry.min(6);
+                warning!("Download failed: {}, retry {}/{} in {}s", err, retry, *DOWNLOAD_RETRIES, backoff_secs);
+                thread::sleep(Duration::from_secs(backoff_secs));
+            },
+        }
+    }
+}
+
+fn finish_part_file(part_file_name: &str, dest_file_name: &str, integrity_verifier: Option<&mut IntegrityVerifier>,
+                    written: u64, expected_size: Option<u64>) -> XResult<()> {
+    if let Some(expected_size) = expected_size {
+        if written != expected_size {
+            fs::remove_file(part_file_name)?;
+            return simple_error!("Download size mismatch, expected: {} bytes, actual: {} bytes", expected_size, written);
+        }
+    }
+    if let Some(integrity_verifier) = integrity_verifier {
+        information!("Verify integrity: {}", integrity_verifier.get_integrity());
+        if !integrity_verifier.verify() {
+            fs::remove_file(part_file_name)?;
+            return simple_error!("Verify integrity failed!");
+        }
+        success!("Verify integrity success.");
+    }
+    fs::rename(part_file_name, dest_file_name)?;
+    Ok(())
+}
+
+/// Returns the total size of the part file
+fn copy_file_to_part(path: &str, part_file_name: &str, integrity_verifier: Option<&mut IntegrityVerifier>,
+                     expected_size: Option<u64>) -> XResult<u64> {
+    let mut source = match File::open(path) {
+        Ok(source) => source,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Err(Box::new(NotFoundError(format!("Open {} failed: {}", path, err)))),
+        Err(err) => return Err(Box::new(NotRetryableError(format!("Open {} failed: {}", path, err)))),
+    };
+    let source_len = source.metadata().map(|md| md.len() as i64).unwrap_or(-1_i64);
+    let mut writer = VerifyWriter { inner: File::create(part_file_name)?, integrity_verifier, written: 0, max_size: expected_size };
+    if let Err(err) = util_io::copy_io_default(&mut source, &mut writer, source_len) {
+        fs::remove_file(part_file_name)?;
+        return Err(Box::new(NotRetryableError(format!("Copy {} failed: {}", path, err))));
+    }
+    Ok(writer.written)
+}
+
+/// Returns the total size of the part file
+fn download_url_to_part(client: &Client, url: &str, part_file_name: &str, mut integrity_verifier: Option<&mut IntegrityVerifier>,
+                        expected_size: Option<u64>) -> XResult<u64> {
+    let downloaded = fs::metadata(part_file_name).map(|md| md.len()).unwrap_or(0);
+    let mut request = client.get(url);
+    if downloaded > 0 {
+        request = request.header(RANGE, format!("bytes={}-", downloaded));
+    }
+    let mut response = request.send()?;
+    let status = response.status();
+    let (part_file, already_written) = if downloaded > 0 && status == StatusCode::PARTIAL_CONTENT {
+        information!("Resume download from: {} bytes", downloaded);
+        if let Some(integrity_verifier) = &mut integrity_verifier {
+            integrity_verifier.input_file(part_file_name)?;
+        }
+        (OpenOptions::new().append(true).open(part_file_name)?, downloaded)
+    } else if status == StatusCode::RANGE_NOT_SATISFIABLE {
+        // part file is broken or the remote file changed, download from start
+        fs::remove_file(part_file_name)?;
+        return simple_error!("Range not satisfiable, restart download: {}", url);
+    } else if status.is_success() {
+        (File::create(part_file_name)?, 0)
+    } else if status == StatusCode::NOT_FOUND {
+        return Err(Box::new(NotFoundError(format!("Download {} failed, HTTP status: {}", url, status))));
+    } else if status.is_client_error() && status != StatusCode::REQUEST_TIMEOUT && status != StatusCode::TOO_MANY_REQUESTS {
+        return Err(Box::new(NotRetryableError(format!("Download {} failed, HTTP status: {}", url, status))));
+    } else {
+        return simple_error!("Download {} failed, HTTP status: {}", url, status);
+    };
+    let header_content_length: i64 = match response.headers().get(CONTENT_LENGTH) {
         None => -1_i64, Some(len_value) => {
             let len_str = len_value.to_str().unwrap_or_else(|err| {
                 warning!("Get content length for {:?}, error: {}", len_value, err);
@@ -23,13 +198,38 @@ pub fn download_url(url: &str, dest: &mut File) -> XResult<()> {
     if *VERBOSE {
         debugging!("Content-Length: {}", header_content_length);
     }
-    util_io::copy_io_default(&mut response, dest, header_content_length)?;
-    Ok(())
+    if let (Some(expected_size), true) = (expected_size, header_content_length >= 0) {
+        if already_written + header_content_length as u64 > expected_size {
+            fs::remove_file(part_file_name)?;
+            return Err(Box::new(NotRetryableError(format!("Content-Length: {} exceeds advertised size: {} bytes",
+                                                          header_content_length, expected_size))));
+        }
+    }
+    let mut writer = VerifyWriter {
+        inner: part_file,
+        integrity_verifier,
+        written: already_written,
+        max_size: expected_size,
+    };
+    let copy_result = util_io::copy_io_default(&mut response, &mut writer, header_content_length);
+    if let Err(err) = copy_result {
+        if err.kind() == io::ErrorKind::InvalidData {
+            fs::remove_file(part_file_name)?;
+            return Err(Box::new(NotRetryableError(err.to_string())));
+        }
+        return Err(Box::new(err));
+    }
+    let written = writer.written - already_written;
+    if header_content_length >= 0 && written != header_content_length as u64 {
+        return simple_error!("Download incomplete, expected: {} bytes, actual: {} bytes", header_content_length, written);
+    }
+    Ok(writer.written)
 }
 
+/// Response body of `url`, non 2xx status is an error
 pub fn get_url_content(url: &str) -> XResult<String> {
     if *VERBOSE {
         debugging!("Get URL: {}", url);
     }
-    Ok(reqwest::blocking::get(url)?.text()?)
+    Ok(build_client()?.get(url).send()?.error_for_status()?.text()?)
 }
@@ -0,0 +1,274 @@
+//! Zero-config mode, when there is no build.json, the builder is inferred from the project file
+//! in the current or parent dirs, `pom.xml` (maven), `build.gradle(.kts)` (gradle) or `build.xml` (ant).
+//!
+//! Builder version is the wrapper version when `mvnw`/`gradlew` properties exist, otherwise the default version.
+//! Java version is from `.java-version`, `.sdkmanrc` or `.tool-versions`, then the project file:
+//! ```text
+//! pom.xml        maven.compiler.release, <release>, java.version, maven.compiler.source, maven.compiler.target
+//! build.gradle   JavaLanguageVersion.of(17), jvmToolchain(17), JavaVersion.VERSION_17, sourceCompatibility = '17'
+//! build.xml      javac release="8", source="1.8", target="1.8"
+//! ```
+//! then the default version.
+use std::{fs, path::Path};
+
+use crate::build_json::{BuildJson, BuildJsonBuilder};
+use crate::java_version::JavaVersionReq;
+use crate::{java_vendor, java_version_file, wrapper};
+use crate::tool::BuilderName;
+use crate::misc::VERBOSE;
+
+pub const DEFAULT_JAVA_VERSION: &str = "17";
+const DEFAULT_MAVEN_VERSION: &str = "3.9.6";
+const DEFAULT_GRADLE_VERSION: &str = "8.5";
+const DEFAULT_ANT_VERSION: &str = "1.10.14";
+
+/// Project files in precedence order
+const PROJECT_FILES: [(&str, BuilderName); 4] = [
+    ("pom.xml", BuilderName::Maven),
+    ("build.gradle.kts", BuilderName::Gradle),
+    ("build.gradle", BuilderName::Gradle),
+    ("build.xml", BuilderName::Ant),
+];
+
+/// Infer build.json from the project file, returns build.json and the project dir
+pub fn infer_build_json() -> Option<(BuildJson, String)> {
+    let current_dir = fs::canonicalize(".").ok()?;
+    let (project_file_name, builder_name, project_dir) = current_dir.ancestors().find_map(|dir| {
+        PROJECT_FILES.iter()
+            .find(|(project_file_name, _)| dir.join(project_file_name).is_file())
+            .and_then(|(project_file_name, builder_name)| Some((*project_file_name, *builder_name, dir.to_str()?.to_string())))
+    })?;
+    warning!("Cannot find build.json, infer from: {}/{}", project_dir, project_file_name);
+
+    let builder_version = match wrapper::find_wrapper_builder(&project_dir) {
+        Ok(Some(wrapper_builder)) if wrapper_builder.name == builder_name.name() => {
+            information!("Infer builder: {} {} from {} wrapper", builder_name.name(), wrapper_builder.version, builder_name.name());
+            wrapper_builder.version
+        },
+        wrapper_builder => {
+            if let Err(err) = wrapper_builder {
+                warning!("Read wrapper failed: {}", err);
+            }
+            let builder_version = get_default_builder_version(builder_name).to_string();
+            information!("Infer builder: {} {} (default version)", builder_name.name(), builder_version);
+            builder_version
+        },
+    };
+    let mut build_json = BuildJson {
+        builder: Some(BuildJsonBuilder { name: builder_name.name().to_string(), version: builder_version }),
+        ..Default::default()
+    };
+
+    if let Err(err) = java_version_file::resolve_java_version_file(&mut build_json, &project_dir) {
+        failure!("Resolve java version file failed: {}", err);
+        return None;
+    }
+    if build_json.java.is_none() {
+        match find_project_java_version(&project_dir, project_file_name) {
+            Some((java_version, java_version_file)) => {
+                information!("Infer java: {} from {}", java_version, java_version_file);
+                build_json.java = Some(java_version);
+            },
+            None => {
+                information!("Infer java: {} (default version)", DEFAULT_JAVA_VERSION);
+                build_json.java = Some(DEFAULT_JAVA_VERSION.to_string());
+            },
+        }
+    }
+    Some((build_json, project_dir))
+}
+
+fn get_default_builder_version(builder_name: BuilderName) -> &'static str {
+    match builder_name {
+        BuilderName::Maven => DEFAULT_MAVEN_VERSION,
+        BuilderName::Gradle => DEFAULT_GRADLE_VERSION,
+        _ => DEFAULT_ANT_VERSION,
+    }
+}
+
+/// Java version in the project file, or the same project file in parent dirs, e.g. the parent pom
+fn find_project_java_version(project_dir: &str, project_file_name: &str) -> Option<(String, String)> {
+    for dir in Path::new(project_dir).ancestors() {
+        let project_file = dir.join(project_file_name);
+        let project_content = match fs::read_to_string(&project_file) {
+            Ok(project_content) => project_content, Err(_) => continue,
+        };
+        let java_version = match project_file_name {
+            "pom.xml" => read_pom_java_version(&project_content),
+            "build.xml" => read_ant_java_version(&project_content),
+            _ => read_gradle_java_version(&project_content),
+        };
+        match java_version {
+            Some(java_version) if JavaVersionReq::parse(&java_version).is_some() => {
+                return Some((java_version, project_file.to_str()?.to_string()));
+            },
+            Some(java_version) => warning!("Invalid java version: {} in {}", java_version, project_file.display()),
+            None => if *VERBOSE {
+                debugging!("Java version not found in: {}", project_file.display());
+            },
+        }
+    }
+    None
+}
+
+fn read_pom_java_version(pom_content: &str) -> Option<String> {
+    let pom_content = remove_xml_comments(pom_content);
+    let java_version = ["maven.compiler.release", "release", "java.version", "maven.compiler.source", "maven.compiler.target"]
+        .iter().find_map(|tag| get_xml_tag_value(&pom_content, tag))?;
+    // `${java.version}`, properties in the same pom only
+    let mut java_version = java_version;
+    for _ in 0..3 {
+        match java_version.strip_prefix("${").and_then(|v| v.strip_suffix('}')) {
+            Some(property) => java_version = get_xml_tag_value(&pom_content, property)?,
+            None => break,
+        }
+    }
+    Some(java_version)
+}
+
+/// Java version and vendor of the gradle toolchain in `build.gradle(.kts)` of `project_dir`,
+/// e.g. `JavaLanguageVersion.of(17)`, `vendor = JvmVendorSpec.ADOPTIUM`
+pub fn read_gradle_toolchain(project_dir: &str) -> Option<(String, Option<String>)> {
+    let gradle_content = ["build.gradle.kts", "build.gradle"].iter()
+        .find_map(|gradle_file_name| fs::read_to_string(Path::new(project_dir).join(gradle_file_name)).ok())?;
+    let gradle_content = remove_gradle_comments(&gradle_content);
+    let java_version = read_gradle_toolchain_version(&gradle_content)?;
+    let java_vendor = gradle_content.split_once("JvmVendorSpec.").map(|(_, value)| {
+        value.chars().take_while(|c| c.is_ascii_alphanumeric() || *c == '_').collect::<String>().replace('_', "").to_lowercase()
+    }).filter(|vendor| !vendor.is_empty() && vendor != "matching").map(|vendor| java_vendor::normalize_vendor(&vendor));
+    Some((java_version, java_vendor))
+}
+
+/// `JavaLanguageVersion.of(17)`, `jvmToolchain(17)`, `JavaVersion.VERSION_1_8`, `sourceCompatibility = '1.8'`
+fn read_gradle_java_version(gradle_content: &str) -> Option<String> {
+    let gradle_content = remove_gradle_comments(gradle_content);
+    if let Some(java_version) = read_gradle_toolchain_version(&gradle_content) {
+        return Some(java_version);
+    }
+    if let Some((_, value)) = gradle_content.split_once("JavaVersion.VERSION_") {
+        let version: String = value.chars().take_while(|c| c.is_ascii_digit() || *c == '_').collect();
+        return Some(version.replace('_', "."));
+    }
+    for key in ["sourceCompatibility", "targetCompatibility"] {
+        let value = gradle_content.lines().find_map(|l| l.trim().strip_prefix(key)?.trim_start().strip_prefix('='));
+        if let Some(value) = value {
+            return Some(value.trim().trim_matches(|c| c == '"' || c == '\'').to_string());
+        }
+    }
+    None
+}
+
+fn read_gradle_toolchain_version(gradle_content: &str) -> Option<String> {
+    ["JavaLanguageVersion.of(", "jvmToolchain("].iter().find_map(|prefix| {
+        let (value, _) = gradle_content.split_once(prefix)?.1.split_once(')')?;
+        Some(value.trim().trim_matches(|c| c == '"' || c == '\'').to_string())
+    })
+}
+
+fn remove_gradle_comments(gradle_content: &str) -> String {
+    gradle_content.lines().filter(|l| !l.trim_start().starts_with("//")).collect::<Vec<_>>().join("\n")
+}
+
+/// `<javac release="8" ...>`, `source`, `target`, `${...}` is resolved by `<property name="..." value="..."/>`
+fn read_ant_java_version(build_xml_content: &str) -> Option<String> {
+    let build_xml_content = remove_xml_comments(build_xml_content);
+    let javac = build_xml_content.split("<javac").nth(1)?;
+    let javac = javac.split('>').next()?;
+    let java_version = ["release", "source", "target"].iter().find_map(|attr| get_xml_attr_value(javac, attr))?;
+    match java_version.strip_prefix("${").and_then(|v| v.strip_suffix('}')) {
+        Some(property) => {
+            let property_tag = build_xml_content.split("<property").find(|p| get_xml_attr_value(p, "name").as_deref() == Some(property))?;
+            get_xml_attr_value(property_tag.split('>').next()?, "value")
+        },
+        None => Some(java_version),
+    }
+}
+
+fn get_xml_tag_value(xml_content: &str, tag: &str) -> Option<String> {
+    let (_, value) = xml_content.split_once(&format!("<{}>", tag))?;
+    let (value, _) = value.split_once(&format!("</{}>", tag))?;
+    Some(value.trim().to_string()).filter(|v| !v.is_empty())
+}
+
+fn get_xml_attr_value(xml_tag: &str, attr: &str) -> Option<String> {
+    let attr_prefix = format!("{}=\"", attr);
+    let (attr_pos, _) = xml_tag.match_indices(&attr_prefix).find(|(i, _)| xml_tag[..*i].ends_with(char::is_whitespace))?;
+    let value = &xml_tag[attr_pos + attr_prefix.len()..];
+    Some(value.split('"').next()?.trim().to_string())
+}
+
+fn remove_xml_comments(xml_content: &str) -> String {
+    let mut content = String::with_capacity(xml_content.len());
+    let mut rest = xml_content;
+    while let Some(start) = rest.find("<!--") {
+        content.push_str(&rest[..start]);
+        rest = match rest[start..].find("-->") {
+            Some(end) => &rest[start + end + 3..],
+            None => "",
+        };
+    }
+    content.push_str(rest);
+    content
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_pom_java_version() {
+        assert_eq!(read_pom_java_version("<properties><maven.compiler.release>17</maven.compiler.release></properties>"), Some("17".to_string()));
+        assert_eq!(read_pom_java_version("<properties>
+            <!-- <maven.compiler.release>8</maven.compiler.release> -->
+            <java.version>11</java.version>
+            <maven.compiler.source>${java.version}</maven.compiler.source>
+        </properties>"), Some("11".to_string()));
+        assert_eq!(read_pom_java_version("<maven.compiler.release>${jdk.version}</maven.compiler.release><jdk.version>21</jdk.version>"),
+                   Some("21".to_string()));
+        assert_eq!(read_pom_java_version("<configuration><source>1.8</source></configuration><maven.compiler.source>1.8</maven.compiler.source>"),
+                   Some("1.8".to_string()));
+        assert_eq!(read_pom_java_version("<maven.compiler.release>${undefined}</maven.compiler.release>"), None);
+        assert_eq!(read_pom_java_version("<project></project>"), None);
+    }
+
+    #[test]
+    fn test_read_gradle_java_version() {
+        assert_eq!(read_gradle_java_version("java { toolchain { languageVersion = JavaLanguageVersion.of(21) } }"), Some("21".to_string()));
+        assert_eq!(read_gradle_java_version("kotlin {\n    jvmToolchain(17)\n}"), Some("17".to_string()));
+        assert_eq!(read_gradle_java_version("// jvmToolchain(8)\nsourceCompatibility = JavaVersion.VERSION_1_8"), Some("1.8".to_string()));
+        assert_eq!(read_gradle_java_version("sourceCompatibility = '11'\ntargetCompatibility = '11'"), Some("11".to_string()));
+        assert_eq!(read_gradle_java_version("java {\n  sourceCompatibility = \"17\"\n}"), Some("17".to_string()));
+        assert_eq!(read_gradle_java_version("plugins { id 'java' }"), None);
+    }
+
+    #[test]
+    fn test_read_ant_java_version() {
+        assert_eq!(read_ant_java_version(r#"<target><javac srcdir="src" release="8"/></target>"#), Some("8".to_string()));
+        assert_eq!(read_ant_java_version(r#"<property name="java.version" value="11"/>
+            <javac srcdir="src" source="${java.version}" target="${java.version}"/>"#), Some("11".to_string()));
+        assert_eq!(read_ant_java_version(r#"<!-- <javac release="8"/> --><javac srcdir="src"/>"#), None);
+    }
+
+    #[test]
+    fn test_find_project_java_version_and_gradle_toolchain() {
+        let test_dir = std::env::temp_dir().join(format!("buildj-test-infer-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&test_dir);
+        fs::create_dir_all(test_dir.join("module")).unwrap();
+        fs::write(test_dir.join("pom.xml"), "<maven.compiler.release>17</maven.compiler.release>").unwrap();
+        fs::write(test_dir.join("module/pom.xml"), "<artifactId>module</artifactId>").unwrap();
+        // java version from the parent pom
+        let (java_version, java_version_file) = find_project_java_version(test_dir.join("module").to_str().unwrap(), "pom.xml").unwrap();
+        assert_eq!(java_version, "17");
+        assert_eq!(java_version_file, test_dir.join("pom.xml").to_str().unwrap());
+
+        fs::write(test_dir.join("module/build.gradle.kts"), "java {
+    toolchain {
+        languageVersion.set(JavaLanguageVersion.of(21))
+        vendor.set(JvmVendorSpec.GRAAL_VM)
+    }
+}").unwrap();
+        assert_eq!(read_gradle_toolchain(test_dir.join("module").to_str().unwrap()), Some(("21".to_string(), Some("graalvm".to_string()))));
+        assert_eq!(read_gradle_toolchain(test_dir.to_str().unwrap()), None);
+        fs::remove_dir_all(&test_dir).unwrap();
+    }
+}
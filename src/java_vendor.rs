@@ -0,0 +1,99 @@
+//! Java vendor matching, a vendor in build.json is a well known id or alias, e.g. `temurin`, `adoptium`,
+//! `zulu`, `graalvm`, matched against the `IMPLEMENTOR` (or `java.vendor`) of a JDK:
+//! ```text
+//! temurin     Eclipse Adoptium, AdoptOpenJDK   zulu        Azul Systems, Inc.
+//! corretto    Amazon.com Inc.                  graalvm     GraalVM Community, has GRAALVM_VERSION
+//! liberica    BellSoft                         microsoft   Microsoft
+//! sapmachine  SAP SE                           semeru      IBM Corporation, Eclipse OpenJ9
+//! dragonwell  Alibaba                          oracle      Oracle Corporation
+//! ```
+//! Vendors not in the list are matched as a case insensitive substring of the JDK vendor.
+//!
+//! JDKs of a well known vendor are downloaded from registries as tool `<vendor id>-<os>`, os is `linux` or `osx`,
+//! e.g. `temurin-linux`, `graalvm-osx`, named like `jdk-linux` and `openjdk-osx`, HTTP and file registries publish
+//! vendor JDKs by these names, the Adoptium registry provides `temurin-*` only.
+use std::env;
+
+/// Vendor id and lower case keywords of vendor id, aliases and implementor, GraalVM is before Oracle
+const JAVA_VENDORS: &[(&str, &[&str])] = &[
+    ("temurin", &["temurin", "adoptium", "adoptopenjdk"]),
+    ("zulu", &["zulu", "azul"]),
+    ("corretto", &["corretto", "amazon"]),
+    ("graalvm", &["graalvm", "graal"]),
+    ("liberica", &["liberica", "bellsoft"]),
+    ("microsoft", &["microsoft"]),
+    ("sapmachine", &["sapmachine", "sap se"]),
+    ("semeru", &["semeru", "ibm", "openj9"]),
+    ("dragonwell", &["dragonwell", "alibaba"]),
+    ("oracle", &["oracle"]),
+];
+
+/// Vendor id of a JDK vendor, e.g. `Azul Systems, Inc.` -> `zulu`
+pub fn identify_vendor(jdk_vendor: &str) -> Option<&'static str> {
+    let jdk_vendor = jdk_vendor.to_lowercase();
+    JAVA_VENDORS.iter()
+        .find(|(_, keywords)| keywords.iter().any(|keyword| jdk_vendor.contains(keyword)))
+        .map(|(vendor_id, _)| *vendor_id)
+}
+
+/// Vendor id of a requested vendor, e.g. `Adoptium` -> `temurin`, lower cased when not well known
+pub fn normalize_vendor(vendor: &str) -> String {
+    let vendor = vendor.trim().to_lowercase();
+    match JAVA_VENDORS.iter().find(|(_, keywords)| keywords.contains(&vendor.as_str())) {
+        Some((vendor_id, _)) => vendor_id.to_string(),
+        None => vendor,
+    }
+}
+
+/// Tool name of the JDK of `vendor` in registries, e.g. `temurin` -> `temurin-linux`,
+/// `None` when the vendor is not well known or the OS has no JDK in registries
+pub fn get_registry_java_name(vendor: &str) -> Option<String> {
+    let vendor = normalize_vendor(vendor);
+    let (vendor_id, _) = JAVA_VENDORS.iter().find(|(vendor_id, _)| *vendor_id == vendor)?;
+    let os = match env::consts::OS {
+        "linux" => "linux",
+        "macos" => "osx",
+        _ => return None,
+    };
+    Some(format!("{}-{}", vendor_id, os))
+}
+
+pub fn is_vendor_matched(vendor: &str, jdk_vendor: &str) -> bool {
+    let vendor = normalize_vendor(vendor);
+    if JAVA_VENDORS.iter().any(|(vendor_id, _)| *vendor_id == vendor) {
+        identify_vendor(jdk_vendor) == Some(vendor.as_str())
+    } else {
+        !vendor.is_empty() && jdk_vendor.to_lowercase().contains(&vendor)
+    }
+}
+
+/// Index of the first matched vendor, vendors are in preference order, any vendor is `Some(0)` when no vendor assigned
+pub fn get_vendor_rank(vendors: &[String], jdk_vendor: Option<&str>) -> Option<usize> {
+    if vendors.is_empty() {
+        return Some(0);
+    }
+    let jdk_vendor = jdk_vendor?;
+    vendors.iter().position(|vendor| is_vendor_matched(vendor, jdk_vendor))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_vendor() {
+        assert_eq!(normalize_vendor(" Adoptium "), "temurin");
+        assert_eq!(normalize_vendor("Azul"), "zulu");
+        assert_eq!(normalize_vendor("Foo"), "foo");
+    }
+
+    #[test]
+    fn test_get_registry_java_name() {
+        let os = match env::consts::OS {
+            "linux" => "linux", "macos" => "osx", _ => return,
+        };
+        assert_eq!(get_registry_java_name("Adoptium"), Some(format!("temurin-{}", os)));
+        assert_eq!(get_registry_java_name("graalvm"), Some(format!("graalvm-{}", os)));
+        assert_eq!(get_registry_java_name("foo"), None);
+    }
+}
@@ -0,0 +1,269 @@
+//! Java version parsing and matching.
+//!
+//! Versions are legacy `1.8.0_242-b08`, `8u242` or JEP 223 `17.0.2+8`, legacy `1.x` is normalized to `x`,
+//! so `1.8` and `8` are the same version, `1.8.0_242` is `8.0.242`.
+//!
+//! Requests are one or more space or comma separated conditions, all must match:
+//! ```text
+//! 17, 17.x, =17   17 with any update        17.0.2   17.0.2 with any build
+//! >=11, >11       11 or later, 12 or later  <17, <=17  before 17, 17 or before
+//! ~17.0.3         17.0.3 or later in 17.0   ^17.0.3    17.0.3 or later in 17
+//! ```
+use std::{cmp::Ordering, fmt};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct JavaVersion {
+    /// Normalized components: feature, interim, update, patch, e.g. `1.8.0_242` -> `[8, 0, 242]`
+    pub components: Vec<u32>,
+    pub build: u32,
+}
+
+/// Versions in `[lower, upper)`, compared by components
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct JavaVersionReq {
+    lower: Option<Vec<u32>>,
+    upper: Option<Vec<u32>>,
+}
+
+impl JavaVersion {
+    pub fn parse(version: &str) -> Option<JavaVersion> {
+        let version = version.trim();
+        // `17.0.2+8-LTS`, `1.8.0_242-b08`, `11.0.2-ea+9`
+        let (version_part, build_part) = match version.find('+') {
+            Some(i) => (&version[..i], Some(&version[i + 1..])),
+            None => (version, None),
+        };
+        let (version_part, pre_part) = match version_part.find('-') {
+            Some(i) => (&version_part[..i], Some(&version_part[i + 1..])),
+            None => (version_part, None),
+        };
+        let build = build_part.or_else(|| pre_part.and_then(|p| p.strip_prefix('b')))
+            .and_then(parse_leading_u32).unwrap_or(0);
+        // `8u242` -> `8.0.242`, `1.8.0_242` -> `1.8.0.242`
+        let version_part = match version_part.find('u') {
+            Some(i) => format!("{}.0.{}", &version_part[..i], &version_part[i + 1..]),
+            None => version_part.replace('_', "."),
+        };
+        let mut components = parse_components(&version_part)?;
+        // `1.8.0.242` -> `8.0.242`
+        if components.len() > 1 && components[0] == 1 {
+            components.remove(0);
+        }
+        Some(JavaVersion { components, build })
+    }
+}
+
+impl fmt::Display for JavaVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", join_components(&self.components))?;
+        if self.build > 0 {
+            write!(f, "+{}", self.build)?;
+        }
+        Ok(())
+    }
+}
+
+impl PartialOrd for JavaVersion {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for JavaVersion {
+    fn cmp(&self, other: &Self) -> Ordering {
+        compare_components(&self.components, &other.components).then(self.build.cmp(&other.build))
+    }
+}
+
+impl JavaVersionReq {
+    pub fn parse(version_req: &str) -> Option<JavaVersionReq> {
+        let mut java_version_req = JavaVersionReq::default();
+        let mut has_condition = false;
+        for condition in version_req.split(|c: char| c == ',' || c.is_whitespace()).filter(|c| !c.is_empty()) {
+            java_version_req.restrict(parse_condition(condition)?);
+            has_condition = true;
+        }
+        iff!(has_condition, Some(java_version_req), None)
+    }
+
+    pub fn matches(&self, java_version: &JavaVersion) -> bool {
+        let components = &java_version.components;
+        self.lower.as_ref().map(|lower| compare_components(components, lower) != Ordering::Less).unwrap_or(true)
+            && self.upper.as_ref().map(|upper| compare_components(components, upper) == Ordering::Less).unwrap_or(true)
+    }
+
+    /// `Some(17)` when request is any version of one feature, e.g. `17`, `1.8`, `17.x`
+    pub fn get_feature_only(&self) -> Option<u32> {
+        match (&self.lower, &self.upper) {
+            (Some(lower), Some(upper)) if lower.len() == 1 && upper.len() == 1 && upper[0] == lower[0] + 1 => Some(lower[0]),
+            _ => None,
+        }
+    }
+
+    /// Maven style version range, e.g. `[17.0.3,17.1)`, `[11,)`
+    pub fn to_version_range(&self) -> String {
+        format!("{}{},{})",
+                iff!(self.lower.is_some(), "[", "("),
+                self.lower.as_ref().map(|lower| join_components(lower)).unwrap_or_default(),
+                self.upper.as_ref().map(|upper| join_components(upper)).unwrap_or_default())
+    }
+
+    /// Intersect with another range
+    fn restrict(&mut self, other: JavaVersionReq) {
+        if let Some(lower) = other.lower {
+            if self.lower.as_ref().map(|l| compare_components(&lower, l) == Ordering::Greater).unwrap_or(true) {
+                self.lower = Some(lower);
+            }
+        }
+        if let Some(upper) = other.upper {
+            if self.upper.as_ref().map(|u| compare_components(&upper, u) == Ordering::Less).unwrap_or(true) {
+                self.upper = Some(upper);
+            }
+        }
+    }
+}
+
+impl fmt::Display for JavaVersionReq {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.to_version_range())
+    }
+}
+
+/// Condition to `[lower, upper)`
+fn parse_condition(condition: &str) -> Option<JavaVersionReq> {
+    // two chars operators first
+    let (op, version) = match [">=", "<=", ">", "<", "=", "~", "^"].iter()
+        .find_map(|op| condition.strip_prefix(op).map(|version| (*op, version))) {
+        Some((op, version)) => (op, version),
+        None => ("=", condition),
+    };
+    let components = parse_req_components(version)?;
+    let (lower, upper) = match op {
+        ">=" => (Some(components), None),
+        ">" => (Some(next_components(&components)), None),
+        "<=" => (None, Some(next_components(&components))),
+        "<" => (None, Some(components)),
+        // `~17.0.3` -> `[17.0.3, 17.1)`, `~17` -> `[17, 18)`
+        "~" => {
+            let upper = next_components(&components[..components.len().clamp(1, 2)]);
+            (Some(components), Some(upper))
+        },
+        "^" => {
+            let upper = next_components(&components[..1]);
+            (Some(components), Some(upper))
+        },
+        _ => {
+            let upper = next_components(&components);
+            (Some(components), Some(upper))
+        },
+    };
+    Some(JavaVersionReq { lower, upper })
+}
+
+/// `17.x`, `17.*`, `1.8`, `17.0.2`
+fn parse_req_components(version: &str) -> Option<Vec<u32>> {
+    let version = version.trim_end_matches(".x").trim_end_matches(".*");
+    if version.is_empty() || version == "x" || version == "*" {
+        return None;
+    }
+    Some(JavaVersion::parse(version)?.components)
+}
+
+fn parse_components(version: &str) -> Option<Vec<u32>> {
+    let mut components = vec![];
+    for component in version.split('.') {
+        components.push(component.parse::<u32>().ok()?);
+    }
+    iff!(components.is_empty(), None, Some(components))
+}
+
+fn parse_leading_u32(s: &str) -> Option<u32> {
+    let digits: String = s.chars().take_while(|c| c.is_ascii_digit()).collect();
+    digits.parse().ok()
+}
+
+fn next_components(components: &[u32]) -> Vec<u32> {
+    let mut next = components.to_vec();
+    if let Some(last) = next.last_mut() {
+        *last += 1;
+    }
+    next
+}
+
+/// Missing components are 0, `17` == `17.0.0`
+fn compare_components(a: &[u32], b: &[u32]) -> Ordering {
+    for i in 0..a.len().max(b.len()) {
+        let ordering = a.get(i).unwrap_or(&0).cmp(b.get(i).unwrap_or(&0));
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+    Ordering::Equal
+}
+
+fn join_components(components: &[u32]) -> String {
+    components.iter().map(|c| c.to_string()).collect::<Vec<_>>().join(".")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(version_req: &str, version: &str) -> bool {
+        JavaVersionReq::parse(version_req).unwrap().matches(&JavaVersion::parse(version).unwrap())
+    }
+
+    #[test]
+    fn test_parse_java_version() {
+        assert_eq!(JavaVersion::parse("1.8.0_242-b08"), Some(JavaVersion { components: vec![8, 0, 242], build: 8 }));
+        assert_eq!(JavaVersion::parse("8u242"), Some(JavaVersion { components: vec![8, 0, 242], build: 0 }));
+        assert_eq!(JavaVersion::parse("17.0.2+8-LTS"), Some(JavaVersion { components: vec![17, 0, 2], build: 8 }));
+        assert_eq!(JavaVersion::parse("1.8"), JavaVersion::parse("8"));
+        assert_eq!(JavaVersion::parse(">=11"), None);
+        assert_eq!(JavaVersion::parse("17.x"), None);
+    }
+
+    #[test]
+    fn test_java_version_ord() {
+        assert!(JavaVersion::parse("17.0.10").unwrap() > JavaVersion::parse("17.0.9").unwrap());
+        assert!(JavaVersion::parse("17.0.2+8").unwrap() > JavaVersion::parse("17.0.2").unwrap());
+        assert!(JavaVersion::parse("11").unwrap() > JavaVersion::parse("1.8.0_392").unwrap());
+        assert_eq!(JavaVersion::parse("17").unwrap().cmp(&JavaVersion::parse("17.0.0").unwrap()), Ordering::Equal);
+    }
+
+    #[test]
+    fn test_java_version_req_matches() {
+        assert!(matches("17", "17.0.2+8"));
+        assert!(matches("1.8", "1.8.0_242"));
+        assert!(matches("8", "1.8.0_242"));
+        assert!(!matches("17", "11.0.2"));
+        assert!(matches("17.x", "17.0.10"));
+        assert!(matches("17.0.2", "17.0.2+8"));
+        assert!(!matches("17.0.2", "17.0.20"));
+        assert!(matches(">=11", "21"));
+        assert!(!matches(">=11", "1.8.0_392"));
+        assert!(matches(">11", "12"));
+        assert!(!matches(">11", "11.0.20"));
+        assert!(matches("<=17", "17.0.9"));
+        assert!(!matches("<17", "17"));
+        assert!(matches(">=11 <17", "11.0.2"));
+        assert!(!matches(">=11, <17", "17.0.1"));
+        assert!(matches("~17.0.3", "17.0.9"));
+        assert!(!matches("~17.0.3", "17.1.0"));
+        assert!(matches("^17.0.3", "17.1.0"));
+        assert!(!matches("^17.0.3", "18"));
+    }
+
+    #[test]
+    fn test_java_version_req() {
+        assert_eq!(JavaVersionReq::parse(""), None);
+        assert_eq!(JavaVersionReq::parse("x"), None);
+        assert_eq!(JavaVersionReq::parse(">=abc"), None);
+        assert_eq!(JavaVersionReq::parse("17").unwrap().get_feature_only(), Some(17));
+        assert_eq!(JavaVersionReq::parse("1.8.x").unwrap().get_feature_only(), Some(8));
+        assert_eq!(JavaVersionReq::parse(">=17").unwrap().get_feature_only(), None);
+        assert_eq!(JavaVersionReq::parse("~17.0.3").unwrap().to_version_range(), "[17.0.3,17.1)");
+        assert_eq!(JavaVersionReq::parse(">=11").unwrap().to_version_range(), "[11,)");
+        assert_eq!(JavaVersionReq::parse("<17").unwrap().to_version_range(), "(,17)");
+    }
+}
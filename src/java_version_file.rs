@@ -0,0 +1,188 @@
+//! Java version from the version files of other tools, used when build.json (or `BUILDJ_JAVA`) has no `java`,
+//! the first found file in the project dir is used:
+//! ```text
+//! .java-version    jenv, e.g. `17`, `temurin64-17.0.2`
+//! .sdkmanrc        SDKMAN, e.g. `java=17.0.2-tem`
+//! .tool-versions   asdf, e.g. `java temurin-17.0.2+8`
+//! ```
+//! The vendor in the version, e.g. `tem`, `temurin`, is used as java vendor when it is a well known vendor.
+use std::{fs, path::Path};
+use rust_util::XResult;
+
+use crate::build_json::BuildJson;
+use crate::java_version::JavaVersionReq;
+use crate::java_vendor;
+use crate::misc::VERBOSE;
+
+pub const JAVA_VERSION_FILE: &str = ".java-version";
+pub const SDKMANRC: &str = ".sdkmanrc";
+pub const TOOL_VERSIONS: &str = ".tool-versions";
+
+/// Java version and vendor id
+type JavaVersionAndVendor = (String, Option<String>);
+type ParseVersionFile = fn(&str) -> Option<JavaVersionAndVendor>;
+
+/// In precedence order
+const JAVA_VERSION_FILES: [(&str, ParseVersionFile); 3] = [
+    (JAVA_VERSION_FILE, parse_java_version_file),
+    (SDKMANRC, parse_sdkmanrc),
+    (TOOL_VERSIONS, parse_tool_versions),
+];
+
+/// SDKMAN identifier suffix and vendor id, e.g. `17.0.2-tem`, other suffixes are identified by `java_vendor`
+const SDKMAN_VENDORS: [(&str, &str); 6] = [
+    ("tem", "temurin"),
+    ("amzn", "corretto"),
+    ("grl", "graalvm"),
+    ("nik", "liberica"),
+    ("librca", "liberica"),
+    ("sapmchn", "sapmachine"),
+];
+
+/// Fill missing `java` of build.json from `.java-version`, `.sdkmanrc` or `.tool-versions` in `project_dir`
+pub fn resolve_java_version_file(build_json: &mut BuildJson, project_dir: &str) -> XResult<()> {
+    if build_json.java.is_some() {
+        return Ok(());
+    }
+    for (version_file_name, parse_version_file) in &JAVA_VERSION_FILES {
+        let version_file = format!("{}/{}", project_dir, version_file_name);
+        if !Path::new(&version_file).is_file() {
+            continue;
+        }
+        if *VERBOSE {
+            debugging!("Find java version file: {}", version_file);
+        }
+        let (version, vendor) = match parse_version_file(&fs::read_to_string(&version_file)?) {
+            Some(version_and_vendor) => version_and_vendor, None => continue,
+        };
+        if JavaVersionReq::parse(&version).is_none() {
+            return simple_error!("Cannot parse java version from {}: {}", version_file, version);
+        }
+        success!("Find java version: {}{} in {}", version, vendor.as_ref().map(|v| format!(" (vendor: {})", v)).unwrap_or_default(), version_file_name);
+        build_json.java = Some(version);
+        build_json.java_vendors = vendor.into_iter().collect();
+        return Ok(());
+    }
+    Ok(())
+}
+
+/// First line, e.g. `17`, `temurin64-17.0.2`, `openjdk64-11.0.2`
+fn parse_java_version_file(content: &str) -> Option<JavaVersionAndVendor> {
+    let line = content.lines().map(|l| l.trim()).find(|l| !l.is_empty() && !l.starts_with('#'))?;
+    Some(split_vendor_and_version(line))
+}
+
+/// `java=17.0.2-tem`, `java=22.3.r17-grl`
+fn parse_sdkmanrc(content: &str) -> Option<JavaVersionAndVendor> {
+    let java = content.lines().map(|l| l.trim()).filter(|l| !l.starts_with('#'))
+        .find_map(|l| l.split_once('=').filter(|(key, _)| key.trim() == "java").map(|(_, value)| value.trim()))?;
+    let (version, vendor) = match java.rsplit_once('-') {
+        Some((version, identifier)) => (version, SDKMAN_VENDORS.iter().find(|(i, _)| *i == identifier)
+            .map(|(_, vendor_id)| *vendor_id).or_else(|| java_vendor::identify_vendor(identifier))),
+        None => (java, None),
+    };
+    Some((normalize_version(version), vendor.map(|v| v.to_string())))
+}
+
+/// `java temurin-17.0.2+8`, the first version is used when fallback versions are listed
+fn parse_tool_versions(content: &str) -> Option<JavaVersionAndVendor> {
+    let java = content.lines().map(|l| l.split('#').next().unwrap_or("")).find_map(|l| {
+        let mut tokens = l.split_whitespace();
+        iff!(tokens.next() == Some("java"), tokens.next(), None)
+    })?;
+    if java == "system" {
+        return None;
+    }
+    let (version, vendor) = split_vendor_and_version(java);
+    // asdf zulu version is the zulu version, e.g. `zulu-17.32.13`, only the feature is the java version
+    if vendor.as_deref() == Some("zulu") {
+        return Some((version.split('.').next().unwrap_or(&version).to_string(), vendor));
+    }
+    Some((version, vendor))
+}
+
+/// `temurin-17.0.2+8` -> `17.0.2+8`, `temurin`, vendor ends at the first `-` before a digit
+fn split_vendor_and_version(name: &str) -> JavaVersionAndVendor {
+    let version_pos = name.char_indices()
+        .find(|(i, c)| *c == '-' && name[i + 1..].starts_with(|c: char| c.is_ascii_digit()))
+        .map(|(i, _)| i);
+    match version_pos {
+        Some(i) => (normalize_version(&name[i + 1..]), java_vendor::identify_vendor(&name[..i]).map(|v| v.to_string())),
+        None => (normalize_version(name), None),
+    }
+}
+
+/// Java version of GraalVM releases, e.g. `22.3.0+java17`, `22.3.r17` -> `17`,
+/// vendor versions, e.g. Corretto `17.0.3.6.1`, are cut to `17.0.3`
+fn normalize_version(version: &str) -> String {
+    if let Some((_, java)) = version.split_once("+java") {
+        return java.to_string();
+    }
+    if let Some((_, java)) = version.split_once(".r").filter(|(_, java)| java.chars().all(|c| c.is_ascii_digit())) {
+        return java.to_string();
+    }
+    let components = version.split('+').next().unwrap_or(version).split('.').collect::<Vec<_>>();
+    iff!(components.len() > 3, components[..3].join("."), version.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn version_and_vendor(version: &str, vendor: Option<&str>) -> Option<JavaVersionAndVendor> {
+        Some((version.to_string(), vendor.map(|v| v.to_string())))
+    }
+
+    #[test]
+    fn test_parse_java_version_file() {
+        assert_eq!(parse_java_version_file("17\n"), version_and_vendor("17", None));
+        assert_eq!(parse_java_version_file("# jenv\n\ntemurin64-17.0.2\n"), version_and_vendor("17.0.2", Some("temurin")));
+        assert_eq!(parse_java_version_file("openjdk64-11.0.2"), version_and_vendor("11.0.2", None));
+        assert_eq!(parse_java_version_file("corretto64-17.0.3.6.1"), version_and_vendor("17.0.3", Some("corretto")));
+        assert_eq!(parse_java_version_file("\n"), None);
+    }
+
+    #[test]
+    fn test_parse_sdkmanrc() {
+        assert_eq!(parse_sdkmanrc("# Enable auto-env\njava=17.0.2-tem\nmaven=3.9.6\n"), version_and_vendor("17.0.2", Some("temurin")));
+        assert_eq!(parse_sdkmanrc("java = 22.3.r17-grl"), version_and_vendor("17", Some("graalvm")));
+        assert_eq!(parse_sdkmanrc("java=17.0.3.6.1-amzn"), version_and_vendor("17.0.3", Some("corretto")));
+        assert_eq!(parse_sdkmanrc("java=21.0.1-zulu"), version_and_vendor("21.0.1", Some("zulu")));
+        assert_eq!(parse_sdkmanrc("java=21.0.1-open"), version_and_vendor("21.0.1", None));
+        assert_eq!(parse_sdkmanrc("java=17"), version_and_vendor("17", None));
+        assert_eq!(parse_sdkmanrc("#java=17.0.2-tem\nmaven=3.9.6"), None);
+    }
+
+    #[test]
+    fn test_parse_tool_versions() {
+        assert_eq!(parse_tool_versions("nodejs 20.1.0\njava temurin-17.0.2+8 # lts\n"), version_and_vendor("17.0.2+8", Some("temurin")));
+        assert_eq!(parse_tool_versions("java adoptopenjdk-11.0.2+9 temurin-17.0.2+8"), version_and_vendor("11.0.2+9", Some("temurin")));
+        assert_eq!(parse_tool_versions("java zulu-17.32.13"), version_and_vendor("17", Some("zulu")));
+        assert_eq!(parse_tool_versions("java graalvm-22.3.0+java17"), version_and_vendor("17", Some("graalvm")));
+        assert_eq!(parse_tool_versions("java openjdk-21"), version_and_vendor("21", None));
+        assert_eq!(parse_tool_versions("java system"), None);
+        assert_eq!(parse_tool_versions("# java temurin-17.0.2+8"), None);
+    }
+
+    #[test]
+    fn test_resolve_java_version_file() {
+        let test_dir = std::env::temp_dir().join(format!("buildj-test-java-version-file-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&test_dir);
+        fs::create_dir_all(&test_dir).unwrap();
+        let project_dir = test_dir.to_str().unwrap();
+        fs::write(test_dir.join(TOOL_VERSIONS), "java temurin-17.0.2+8").unwrap();
+        fs::write(test_dir.join(SDKMANRC), "java=21.0.1-grl").unwrap();
+        let mut build_json = BuildJson::default();
+        resolve_java_version_file(&mut build_json, project_dir).unwrap();
+        // .sdkmanrc is before .tool-versions
+        assert_eq!((build_json.java.as_deref(), build_json.java_vendors), (Some("21.0.1"), vec!["graalvm".to_string()]));
+
+        let mut build_json = BuildJson { java: Some("11".to_string()), ..Default::default() };
+        resolve_java_version_file(&mut build_json, project_dir).unwrap();
+        assert_eq!(build_json.java.as_deref(), Some("11"));
+
+        fs::write(test_dir.join(JAVA_VERSION_FILE), "abc").unwrap();
+        assert!(resolve_java_version_file(&mut BuildJson::default(), project_dir).is_err());
+        fs::remove_dir_all(&test_dir).unwrap();
+    }
+}
@@ -1,7 +1,10 @@
-use std::{collections::HashMap, env, fs, str, path::Path, process::Command};
+use std::{cmp::Reverse, collections::{HashMap, HashSet}, env, fs, str, path::Path, process::Command};
 use rust_util::util_os;
 use rust_util::util_env;
-use crate::{local_util, tool, misc::VERBOSE};
+use crate::{local_util, tool, manifest::{self, InstallState}, lockfile::LockedTool, misc::VERBOSE};
+use crate::java_version::{JavaVersion, JavaVersionReq};
+use crate::java_vendor;
+use crate::jdk_inventory::{self, JdkInfo, JdkInventory};
 use plist::Value;
 
 const PATH: &str = "PATH";
@@ -15,126 +18,354 @@ const MACOS_LIBEXEC_JAVAHOME: &str = "/usr/libexec/java_home";
 
 pub const LOCAL_JAVA_HOME_BASE_DIR: &str = ".jssp/jdks";
 
+/// JDK search roots on Linux, `~/` is the user home
+const DEFAULT_JDK_SEARCH_PATHS: [&str; 6] = [
+    "/usr/lib/jvm",
+    "~/.sdkman/candidates/java",
+    "~/.asdf/installs/java",
+    "~/.jenv/versions",
+    "/usr/java",
+    "/opt",
+];
+
 lazy_static! {
     pub static ref BUILDJ_JAVA_NAME: Option<String> = env::var("BUILDJ_JAVA_NAME").ok();
+    pub static ref NO_SYSTEM_JDK: bool = util_env::is_env_on("BUILDJ_NO_SYSTEM_JDK");
+    pub static ref JDK_SEARCH_PATHS: Option<String> = env::var("BUILDJ_JDK_SEARCH_PATHS").ok();
+}
+
+/// Installed JDK matching `version` and `vendors`, download when not found, `vendors` are in preference order
+pub fn get_java_home(version: &str, vendors: &[String]) -> Option<String> {
+    if let Some(j) = get_installed_java_home(version, vendors) {
+        return Some(j);
+    }
+    iff!(get_cloud_java(version, vendors), get_local_java_home(version, vendors), None)
 }
 
-pub fn get_java_home(version: &str) -> Option<String> {
-    match get_macos_java_home(version) {
+/// JDK pinned in `build.lock.json`, installed from the locked URL when not found
+pub fn get_locked_java_home(locked_java: &LockedTool, download: bool) -> Option<String> {
+    if let Some(j) = find_locked_java_home(locked_java) {
+        return Some(j);
+    }
+    if !download {
+        return None;
+    }
+    let local_java_home_base_dir = local_util::get_user_home_dir(LOCAL_JAVA_HOME_BASE_DIR).ok()?;
+    information!("Install locked java: {} {}", locked_java.name, locked_java.version);
+    if let Err(err) = tool::install_tool_package(&local_java_home_base_dir, false, &locked_java.to_tool_package_detail()) {
+        failure!("Install locked java: {} {} failed: {}", locked_java.name, locked_java.version, err);
+        return None;
+    }
+    find_locked_java_home(locked_java)
+}
+
+fn find_locked_java_home(locked_java: &LockedTool) -> Option<String> {
+    let local_java_home_base_dir = local_util::get_user_home_dir(LOCAL_JAVA_HOME_BASE_DIR).ok()?;
+    let paths = fs::read_dir(Path::new(&local_java_home_base_dir)).ok()?;
+    for dir_entry in paths.flatten() {
+        let p = dir_entry.path();
+        let p = match p.to_str() {
+            Some(p) if dir_entry.path().is_dir() => p.to_string(), _ => continue,
+        };
+        if let InstallState::Installed(install_manifest) = manifest::get_install_state(&p) {
+            if locked_java.matches(&install_manifest) {
+                if *VERBOSE {
+                    debugging!("Locked JDK path found: {}", p);
+                }
+                return Some(jdk_inventory::get_java_home_of_dir(&p));
+            }
+        }
+    }
+    None
+}
+
+/// Same as `get_java_home`, but never downloads
+pub fn get_installed_java_home(version: &str, vendors: &[String]) -> Option<String> {
+    if let Some(j) = get_macos_java_home(version, vendors) {
+        return Some(j);
+    }
+    match get_local_java_home(version, vendors) {
         Some(j) => Some(j),
-        None => match get_local_java_home(version) {
-            Some(j) => Some(j),
-            None => iff!(get_cloud_java(version), get_local_java_home(version), None),
-        },
+        None => get_system_java_home(version, vendors),
     }
 }
 
-pub fn get_cloud_java(version: &str) -> bool {
+/// Download JDK, the tool name is from `get_cloud_java_names`
+pub fn get_cloud_java(version: &str, vendors: &[String]) -> bool {
     if !util_os::is_macos_or_linux() {
         return false;
     }
-    let cloud_java_names = match &*BUILDJ_JAVA_NAME {
-        None => if util_os::is_macos() {
-            vec![OPENJDK_MACOS]
-        } else if util_os::is_linux() {
-            vec![JDK_LINUX, OPENJDK_LINUX]
-        } else {
-            vec![]
-        },
-        Some(buildj_java_name) => vec![buildj_java_name.as_str()],
-    };
+    let cloud_java_names = get_cloud_java_names(vendors);
+    if cloud_java_names.is_empty() {
+        failure!("No download source for java: {}{}, only well known vendors are downloaded from registries",
+                 version, get_vendors_message(vendors));
+        return false;
+    }
     let local_java_home_base_dir = match local_util::get_user_home_dir(LOCAL_JAVA_HOME_BASE_DIR) {
         Ok(o) => o,
         Err(_) => return false,
     };
-    for cloud_java_name in cloud_java_names {
+    for cloud_java_name in &cloud_java_names {
         if tool::get_and_extract_tool_package(&local_java_home_base_dir, false, cloud_java_name, version, false).is_ok() {
             return true;
         }
     }
-    failure!("Get java failed, version: {}", version);
+    failure!("Get java failed, version: {}{}, no registry provides: {}", version, get_vendors_message(vendors), cloud_java_names.join(", "));
     false
 }
 
-pub fn get_macos_java_home(version: &str) -> Option<String> {
+/// JDK tool names in registries, see `java_vendor::get_registry_java_name` when vendors are assigned,
+/// e.g. `temurin-linux`, otherwise `BUILDJ_JAVA_NAME` or the default names of the OS
+pub fn get_cloud_java_names(vendors: &[String]) -> Vec<String> {
+    if !vendors.is_empty() {
+        return vendors.iter().filter_map(|vendor| {
+            let registry_java_name = java_vendor::get_registry_java_name(vendor);
+            if registry_java_name.is_none() && *VERBOSE {
+                debugging!("Java vendor: {} has no download source", vendor);
+            }
+            registry_java_name
+        }).collect();
+    }
+    match &*BUILDJ_JAVA_NAME {
+        Some(buildj_java_name) => vec![buildj_java_name.clone()],
+        None => if util_os::is_macos() {
+            vec![OPENJDK_MACOS.to_string()]
+        } else if util_os::is_linux() {
+            vec![JDK_LINUX.to_string(), OPENJDK_LINUX.to_string()]
+        } else {
+            vec![]
+        },
+    }
+}
+
+/// A JDK from `/usr/libexec/java_home -x`
+struct MacosJvm {
+    version: String,
+    vendor: Option<String>,
+    home: String,
+}
+
+pub fn get_macos_java_home(version: &str, vendors: &[String]) -> Option<String> {
     if !util_os::is_macos() || util_env::is_env_on("SKIP_CHECK_JAVA_HOME") {
         return None;
     }
-    let java_home_x = Command::new(MACOS_LIBEXEC_JAVAHOME).arg("-x").output().ok()?;
+    let java_version_req = parse_java_version_req(version)?;
+    let mut matched_java_homes = vec![];
+    for macos_jvm in list_macos_jvms() {
+        debugging!("Check version: {} vs {}", macos_jvm.version, version);
+        let vendor_rank = match java_vendor::get_vendor_rank(vendors, macos_jvm.vendor.as_deref()) {
+            Some(vendor_rank) => vendor_rank, None => continue,
+        };
+        match JavaVersion::parse(&macos_jvm.version) {
+            Some(java_version) if java_version_req.matches(&java_version) => {
+                debugging!("Check version success: {} -> {}", macos_jvm.version, macos_jvm.home);
+                matched_java_homes.push((Reverse(vendor_rank), java_version, macos_jvm.home));
+            },
+            _ => (),
+        }
+    }
+    matched_java_homes.into_iter().max().map(|(_, _, jvm_path)| jvm_path)
+}
+
+fn list_macos_jvms() -> Vec<MacosJvm> {
+    let java_home_x = match Command::new(MACOS_LIBEXEC_JAVAHOME).arg("-x").output() {
+        Ok(java_home_x) => java_home_x, Err(_) => return vec![],
+    };
     let java_home_plist_value = match Value::from_reader_xml(&*java_home_x.stdout) {
         Err(e) => {
             debugging!("Parse java_home outputs failed: {}", e);
-            return None;
+            return vec![];
         }
         Ok(val) => val,
     };
     let java_home_plist_value_array = match java_home_plist_value.as_array() {
         None => {
             debugging!("Covert java_home plist output to array failed: {:?}", java_home_plist_value);
-            return None;
+            return vec![];
         }
         Some(val) => val,
     };
+    let mut macos_jvms = vec![];
     for java_home_plist_item in java_home_plist_value_array {
         debugging!("Checking: {:?}", java_home_plist_item);
         if let Some(jvm_item) = java_home_plist_item.as_dictionary() {
             let jvm_version_value = jvm_item.get("JVMVersion");
             let jvm_home_path_value = jvm_item.get("JVMHomePath");
+            let jvm_vendor = jvm_item.get("JVMVendor").and_then(|v| v.as_string());
             if let (Some(Value::String(jvm_version)), Some(Value::String(jvm_path))) = (jvm_version_value, jvm_home_path_value) {
-                debugging!("Check version: {} vs {}", jvm_version, version);
-                if jvm_version.starts_with(version) {
-                    debugging!("Check version success: {} -> {}", jvm_version, jvm_path);
-                    return Some(jvm_path.into());
-                }
+                macos_jvms.push(MacosJvm {
+                    version: jvm_version.to_string(),
+                    vendor: jvm_vendor.map(|v| v.to_string()),
+                    home: jvm_path.to_string(),
+                });
             }
         }
     }
-    None
+    macos_jvms
 }
 
-pub fn get_local_java_home(version: &str) -> Option<String> {
+/// Highest installed JDK matching `version` of the most preferred vendor, JDK version and vendor are read by `JdkInventory`
+pub fn get_local_java_home(version: &str, vendors: &[String]) -> Option<String> {
+    let java_version_req = parse_java_version_req(version)?;
     let local_java_home_base_dir = local_util::get_user_home_dir(LOCAL_JAVA_HOME_BASE_DIR).ok()?;
-    let paths = fs::read_dir(Path::new(&local_java_home_base_dir)).ok()?;
-    for path in paths {
-        if let Ok(dir_entry) = path {
-            if let Some(p) = dir_entry.path().to_str() {
-                if *VERBOSE {
-                    debugging!("Try match path: {}", p);
-                }
-                let mut path_name = p;
-                if p.ends_with('/') {
-                    path_name = &path_name[..path_name.len() - 1]
-                }
-                if let Some(i) = path_name.rfind('/') {
-                    path_name = &path_name[i + 1..];
-                }
-                let matched_path_opt = if (path_name.starts_with("jdk-") && (&path_name[4..]).starts_with(version))
-                    || (path_name.starts_with("jdk") && (&path_name[3..]).starts_with(version)) {
-                    Some(p)
-                } else {
-                    None
-                };
-                if let Some(matched_path) = matched_path_opt {
-                    if *VERBOSE {
-                        debugging!("Matched JDK path found: {}", matched_path);
-                    }
-                    return if local_util::is_path_exists(matched_path, "Contents/Home") {
-                        Some(format!("{}/{}", matched_path, "Contents/Home"))
-                    } else {
-                        Some(matched_path.to_string())
-                    };
-                }
+    let mut jdk_inventory = JdkInventory::load(&local_java_home_base_dir);
+    let local_jdks = list_local_jdks(&local_java_home_base_dir, &mut jdk_inventory);
+    jdk_inventory.save();
+    find_matched_java_home(&local_jdks, &java_version_req, vendors)
+}
+
+/// Highest system JDK matching `version` on Linux, searched in the JDK search paths, e.g. `/usr/lib/jvm`,
+/// SDKMAN, asdf and jenv, disabled by `BUILDJ_NO_SYSTEM_JDK=1` or standard json#build.js#system_jdk = false
+pub fn get_system_java_home(version: &str, vendors: &[String]) -> Option<String> {
+    if !util_os::is_linux() || !is_system_jdk_enabled() {
+        return None;
+    }
+    let java_version_req = parse_java_version_req(version)?;
+    let local_java_home_base_dir = local_util::get_user_home_dir(LOCAL_JAVA_HOME_BASE_DIR).ok()?;
+    let mut jdk_inventory = JdkInventory::load(&local_java_home_base_dir);
+    let system_jdks = list_system_jdks(&mut jdk_inventory);
+    jdk_inventory.save();
+    find_matched_java_home(&system_jdks, &java_version_req, vendors)
+}
+
+/// All JDKs buildj can use: macOS `java_home`, `~/.jssp/jdks` and system JDKs on Linux, sorted by version
+pub fn list_installed_jdks() -> Vec<JdkInfo> {
+    let local_java_home_base_dir = match local_util::get_user_home_dir(LOCAL_JAVA_HOME_BASE_DIR) {
+        Ok(local_java_home_base_dir) => local_java_home_base_dir, Err(_) => return vec![],
+    };
+    let mut jdk_inventory = JdkInventory::load(&local_java_home_base_dir);
+    let mut jdks = vec![];
+    if util_os::is_macos() && !util_env::is_env_on("SKIP_CHECK_JAVA_HOME") {
+        jdks.extend(list_macos_jvms().iter().filter_map(|macos_jvm| jdk_inventory.get_jdk_info(&macos_jvm.home)));
+    }
+    jdks.extend(list_local_jdks(&local_java_home_base_dir, &mut jdk_inventory));
+    if util_os::is_linux() && is_system_jdk_enabled() {
+        jdks.extend(list_system_jdks(&mut jdk_inventory));
+    }
+    jdk_inventory.save();
+    let mut java_homes = HashSet::new();
+    jdks.retain(|jdk_info| java_homes.insert(fs::canonicalize(&jdk_info.home).unwrap_or_else(|_| jdk_info.home.clone().into())));
+    jdks.sort_by_key(|jdk_info| jdk_info.get_java_version());
+    jdks
+}
+
+/// JDKs in `~/.jssp/jdks`, hidden dirs, e.g. `.staging-*`, and broken JDKs are skipped
+fn list_local_jdks(local_java_home_base_dir: &str, jdk_inventory: &mut JdkInventory) -> Vec<JdkInfo> {
+    let mut local_jdks = vec![];
+    for jdk_dir in local_util::list_sub_dirs(local_java_home_base_dir) {
+        if *VERBOSE {
+            debugging!("Try match path: {}", jdk_dir);
+        }
+        let java_home = jdk_inventory::get_java_home_of_dir(&jdk_dir);
+        if let Some(jdk_info) = jdk_inventory.get_jdk_info(&java_home) {
+            if is_local_java_home_installed(&jdk_dir) {
+                local_jdks.push(jdk_info);
             }
         }
     }
-    None
+    local_jdks
 }
 
-pub fn extract_jdk_and_wait(file_name: &str) {
-    if let Ok(local_java_home_base_dir) = local_util::get_user_home_dir(LOCAL_JAVA_HOME_BASE_DIR) {
-        local_util::extract_package_and_wait(&local_java_home_base_dir, file_name).unwrap_or_else(|err| {
-            failure!("Extract file: {}, failed: {}", file_name, err);
-        });
+fn list_system_jdks(jdk_inventory: &mut JdkInventory) -> Vec<JdkInfo> {
+    let mut jdk_dirs = vec![];
+    for jdk_search_path in get_jdk_search_paths() {
+        if *VERBOSE {
+            debugging!("Search system JDKs in: {}", jdk_search_path);
+        }
+        // search path can be a JDK itself
+        if local_util::is_path_exists(&jdk_search_path, "bin/java") {
+            jdk_dirs.push(jdk_search_path);
+        } else {
+            jdk_dirs.extend(local_util::list_sub_dirs(&jdk_search_path));
+        }
+    }
+    // symlinks, e.g. `/usr/lib/jvm/default-java`, SDKMAN `current`, jenv versions, are the same JDK
+    let mut java_homes: Vec<String> = jdk_dirs.iter()
+        .filter(|jdk_dir| local_util::is_path_exists(jdk_dir, "bin/java"))
+        .filter_map(|jdk_dir| fs::canonicalize(jdk_dir).ok()?.to_str().map(|p| p.to_string()))
+        .collect();
+    java_homes.sort();
+    java_homes.dedup();
+    java_homes.iter().filter_map(|java_home| jdk_inventory.get_jdk_info(java_home)).collect()
+}
+
+/// Preferred vendor, highest version, then path, so the result does not depend on read_dir order
+fn find_matched_java_home(jdk_infos: &[JdkInfo], java_version_req: &JavaVersionReq, vendors: &[String]) -> Option<String> {
+    let mut matched_java_homes = vec![];
+    for jdk_info in jdk_infos {
+        let (java_version, vendor_rank) = match (jdk_info.get_java_version(), java_vendor::get_vendor_rank(vendors, jdk_info.vendor.as_deref())) {
+            (Some(java_version), Some(vendor_rank)) => (java_version, vendor_rank), _ => continue,
+        };
+        if java_version_req.matches(&java_version) {
+            if *VERBOSE {
+                debugging!("Matched JDK path found: {}, version: {}, vendor: {:?}", jdk_info.home, java_version, jdk_info.vendor);
+            }
+            matched_java_homes.push((Reverse(vendor_rank), java_version, jdk_info.home.clone()));
+        }
+    }
+    matched_java_homes.into_iter().max().map(|(_, _, java_home)| java_home)
+}
+
+fn is_system_jdk_enabled() -> bool {
+    if *NO_SYSTEM_JDK {
+        return false;
+    }
+    tool::read_standard_config_object()
+        .map(|standard_config_object| standard_config_object["build.js"]["system_jdk"].as_bool().unwrap_or(true))
+        .unwrap_or(true)
+}
+
+/// `BUILDJ_JDK_SEARCH_PATHS` (`:` separated), or standard json#build.js#jdk_search_paths, or the default paths
+fn get_jdk_search_paths() -> Vec<String> {
+    let jdk_search_paths: Vec<String> = match &*JDK_SEARCH_PATHS {
+        Some(jdk_search_paths) => jdk_search_paths.split(':').map(|p| p.to_string()).collect(),
+        None => match tool::read_standard_config_object() {
+            Ok(standard_config_object) if standard_config_object["build.js"]["jdk_search_paths"].is_array() => {
+                standard_config_object["build.js"]["jdk_search_paths"].members()
+                    .filter_map(|p| p.as_str().map(|p| p.to_string())).collect()
+            },
+            _ => DEFAULT_JDK_SEARCH_PATHS.iter().map(|p| p.to_string()).collect(),
+        },
+    };
+    let user_home = local_util::get_user_home().ok();
+    jdk_search_paths.iter().map(|p| p.trim()).filter(|p| !p.is_empty()).filter_map(|p| {
+        match p.strip_prefix("~/") {
+            Some(sub_path) => user_home.as_ref().map(|user_home| format!("{}/{}", user_home, sub_path)),
+            None => Some(p.to_string()),
+        }
+    }).collect()
+}
+
+/// e.g. ` (vendor: graalvm, temurin)`, empty when no vendor assigned
+pub fn get_vendors_message(vendors: &[String]) -> String {
+    iff!(vendors.is_empty(), String::new(), format!(" (vendor: {})", vendors.join(", ")))
+}
+
+fn parse_java_version_req(version: &str) -> Option<JavaVersionReq> {
+    let java_version_req = JavaVersionReq::parse(version);
+    if java_version_req.is_none() {
+        failure!("Invalid java version: {}", version);
+    }
+    java_version_req
+}
+
+/// JDKs installed by buildj must have a valid install manifest, JDKs copied by hand must have `bin/java`
+fn is_local_java_home_installed(java_home_dir: &str) -> bool {
+    if local_util::is_path_exists(java_home_dir, manifest::INSTALL_MANIFEST_JSON) {
+        return match manifest::get_install_state(java_home_dir) {
+            InstallState::Installed(_) => true,
+            InstallState::Missing | InstallState::Legacy => false,
+            InstallState::Broken(reason) => {
+                warning!("JDK {} is broken: {}", java_home_dir, reason);
+                false
+            },
+        };
+    }
+    if local_util::is_path_exists(java_home_dir, "bin/java") || local_util::is_path_exists(java_home_dir, "Contents/Home/bin/java") {
+        true
+    } else {
+        warning!("JDK {} is broken: bin/java not found", java_home_dir);
+        false
     }
 }
 
@@ -0,0 +1,303 @@
+//! JDK inventory, identifies a JDK by its `release` file (`JAVA_VERSION`, `IMPLEMENTOR`, `OS_ARCH`),
+//! falls back to `bin/java -XshowSettings:properties -version`, then to the dir name.
+//!
+//! Results are cached in `~/.jssp/jdks/.buildj_jdk_inventory.json`, an entry is refreshed when the
+//! modified time of `release` (or `bin/java`) changes.
+use std::{fs, collections::BTreeMap, path::Path, process::Command, time::UNIX_EPOCH};
+use json::JsonValue;
+use rust_util::XResult;
+
+use crate::{local_util, java_version::JavaVersion, misc::VERBOSE};
+
+const JDK_INVENTORY_JSON: &str = ".buildj_jdk_inventory.json";
+const RELEASE_FILE: &str = "release";
+const JAVA_BIN: &str = "bin/java";
+
+#[derive(Clone, Debug)]
+pub struct JdkInfo {
+    pub home: String,
+    /// Raw version, e.g. `1.8.0_242`, `17.0.2+8`
+    pub version: String,
+    /// e.g. `Eclipse Adoptium`, `Azul Systems, Inc.`
+    pub vendor: Option<String>,
+    /// e.g. `x86_64`, `amd64`, `aarch64`
+    pub arch: Option<String>,
+    /// Modified time of the file the info is read from
+    modified: u64,
+}
+
+impl JdkInfo {
+    pub fn get_java_version(&self) -> Option<JavaVersion> {
+        JavaVersion::parse(&self.version)
+    }
+
+    fn parse(home: &str, jdk_info_object: &JsonValue) -> Option<JdkInfo> {
+        Some(JdkInfo {
+            home: home.to_string(),
+            version: jdk_info_object["version"].as_str()?.to_string(),
+            vendor: jdk_info_object["vendor"].as_str().map(|v| v.to_string()),
+            arch: jdk_info_object["arch"].as_str().map(|a| a.to_string()),
+            modified: jdk_info_object["modified"].as_u64().unwrap_or(0),
+        })
+    }
+
+    fn to_json(&self) -> JsonValue {
+        let mut jdk_info_object = object! {
+            "version" => self.version.as_str(),
+            "modified" => self.modified,
+        };
+        if let Some(vendor) = &self.vendor {
+            jdk_info_object["vendor"] = vendor.as_str().into();
+        }
+        if let Some(arch) = &self.arch {
+            jdk_info_object["arch"] = arch.as_str().into();
+        }
+        jdk_info_object
+    }
+}
+
+#[derive(Default)]
+pub struct JdkInventory {
+    inventory_file: Option<String>,
+    jdk_infos: BTreeMap<String, JdkInfo>,
+    changed: bool,
+}
+
+impl JdkInventory {
+    /// Load the cached inventory, empty when cache not exists or is broken
+    pub fn load(base_dir: &str) -> JdkInventory {
+        let inventory_file = format!("{}/{}", base_dir, JDK_INVENTORY_JSON);
+        let mut jdk_inventory = JdkInventory { inventory_file: Some(inventory_file.clone()), ..Default::default() };
+        let inventory_object = match fs::read_to_string(&inventory_file).ok().and_then(|c| json::parse(&c).ok()) {
+            Some(inventory_object) => inventory_object, None => return jdk_inventory,
+        };
+        for (home, jdk_info_object) in inventory_object["jdks"].entries() {
+            if let Some(jdk_info) = JdkInfo::parse(home, jdk_info_object) {
+                jdk_inventory.jdk_infos.insert(home.to_string(), jdk_info);
+            }
+        }
+        jdk_inventory
+    }
+
+    /// Cached info when still fresh, otherwise read `release`, then run `bin/java`
+    pub fn get_jdk_info(&mut self, java_home: &str) -> Option<JdkInfo> {
+        let modified = get_jdk_modified(java_home)?;
+        if let Some(jdk_info) = self.jdk_infos.get(java_home) {
+            if jdk_info.modified == modified {
+                return Some(jdk_info.clone());
+            }
+        }
+        let jdk_info = read_jdk_info(java_home, modified)?;
+        if *VERBOSE {
+            debugging!("JDK found: {}, version: {}, vendor: {:?}, arch: {:?}", java_home, jdk_info.version, jdk_info.vendor, jdk_info.arch);
+        }
+        self.jdk_infos.insert(java_home.to_string(), jdk_info.clone());
+        self.changed = true;
+        Some(jdk_info)
+    }
+
+    /// Write cache when changed, entries of removed JDKs are dropped
+    pub fn save(&mut self) {
+        let inventory_file = match (&self.inventory_file, self.changed) {
+            (Some(inventory_file), true) => inventory_file.clone(),
+            _ => return,
+        };
+        self.jdk_infos.retain(|home, _| Path::new(home).exists());
+        let mut jdks_object = JsonValue::new_object();
+        for (home, jdk_info) in &self.jdk_infos {
+            jdks_object[home.as_str()] = jdk_info.to_json();
+        }
+        // concurrent buildj runs share the cache, system JDKs are cached before any JDK is installed into `~/.jssp/jdks`
+        if let Err(err) = local_util::write_file_atomic(&inventory_file, &json::stringify_pretty(object! { "jdks" => jdks_object }, 4)) {
+            warning!("Write JDK inventory: {} failed: {}", inventory_file, err);
+        }
+        self.changed = false;
+    }
+}
+
+fn get_jdk_modified(java_home: &str) -> Option<u64> {
+    let release_file = format!("{}/{}", java_home, RELEASE_FILE);
+    let java_bin = format!("{}/{}", java_home, JAVA_BIN);
+    let metadata = fs::metadata(&release_file).or_else(|_| fs::metadata(&java_bin)).ok()?;
+    Some(metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?.as_secs())
+}
+
+fn read_jdk_info(java_home: &str, modified: u64) -> Option<JdkInfo> {
+    match read_release_file(java_home) {
+        Ok(Some(jdk_info)) => return Some(JdkInfo { modified, ..jdk_info }),
+        Ok(None) => (),
+        Err(err) => warning!("Read {}/{} failed: {}", java_home, RELEASE_FILE, err),
+    }
+    if let Some(jdk_info) = read_java_settings(java_home) {
+        return Some(JdkInfo { modified, ..jdk_info });
+    }
+    // e.g. `jdk1.8.0_242`, `jdk-17.0.2+8`
+    let dir_name = Path::new(java_home).file_name()?.to_str()?;
+    let version = dir_name.strip_prefix("jdk-").or_else(|| dir_name.strip_prefix("jdk"))?;
+    let version = version.strip_suffix(".jdk").unwrap_or(version);
+    JavaVersion::parse(version)?;
+    Some(JdkInfo { home: java_home.to_string(), version: version.to_string(), vendor: None, arch: None, modified })
+}
+
+/// `release` is `KEY="value"` lines, `Ok(None)` when not exists
+fn read_release_file(java_home: &str) -> XResult<Option<JdkInfo>> {
+    let release_file = format!("{}/{}", java_home, RELEASE_FILE);
+    if !Path::new(&release_file).exists() {
+        return Ok(None);
+    }
+    let mut release = BTreeMap::new();
+    for line in fs::read_to_string(&release_file)?.lines() {
+        if let Some((key, value)) = line.split_once('=') {
+            release.insert(key.trim().to_string(), value.trim().trim_matches('"').to_string());
+        }
+    }
+    // JAVA_RUNTIME_VERSION has the build number, e.g. `17.0.2+8`
+    let version = match release.get("JAVA_RUNTIME_VERSION").or_else(|| release.get("JAVA_VERSION")) {
+        Some(version) => version.clone(),
+        None => return simple_error!("JAVA_VERSION not found"),
+    };
+    Ok(Some(JdkInfo {
+        home: java_home.to_string(),
+        version,
+        // GraalVM by Oracle has `IMPLEMENTOR="Oracle Corporation"`
+        vendor: get_graalvm_vendor(release.contains_key("GRAALVM_VERSION"), release.get("IMPLEMENTOR")),
+        arch: release.get("OS_ARCH").cloned(),
+        modified: 0,
+    }))
+}
+
+/// `java -XshowSettings:properties -version` prints properties to stderr, e.g. `    java.version = 17.0.2`
+fn read_java_settings(java_home: &str) -> Option<JdkInfo> {
+    let java_bin = format!("{}/{}", java_home, JAVA_BIN);
+    if *VERBOSE {
+        debugging!("Run: {} -XshowSettings:properties -version", java_bin);
+    }
+    let output = Command::new(&java_bin).args(["-XshowSettings:properties", "-version"]).output().ok()?;
+    let settings = String::from_utf8_lossy(&output.stderr);
+    let mut properties = BTreeMap::new();
+    for line in settings.lines() {
+        if let Some((key, value)) = line.split_once(" = ") {
+            properties.insert(key.trim().to_string(), value.trim().to_string());
+        }
+    }
+    let version = properties.get("java.runtime.version").or_else(|| properties.get("java.version"))?;
+    Some(JdkInfo {
+        home: java_home.to_string(),
+        version: version.clone(),
+        vendor: get_graalvm_vendor(properties.get("java.vm.name").map(|n| n.contains("GraalVM")).unwrap_or(false),
+                                   properties.get("java.vendor")),
+        arch: properties.get("os.arch").cloned(),
+        modified: 0,
+    })
+}
+
+/// Vendor prefixed with `GraalVM` for GraalVM JDKs, so it is identified as GraalVM, not Oracle
+fn get_graalvm_vendor(is_graalvm: bool, vendor: Option<&String>) -> Option<String> {
+    match vendor {
+        Some(vendor) if is_graalvm && !vendor.contains("GraalVM") => Some(format!("GraalVM, {}", vendor)),
+        None if is_graalvm => Some("GraalVM".to_string()),
+        _ => vendor.cloned(),
+    }
+}
+
+/// Java home of a JDK dir, `Contents/Home` on macOS
+pub fn get_java_home_of_dir(jdk_dir: &str) -> String {
+    if local_util::is_path_exists(jdk_dir, "Contents/Home") {
+        format!("{}/{}", jdk_dir, "Contents/Home")
+    } else {
+        jdk_dir.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::java_vendor;
+
+    fn new_test_dir(name: &str) -> String {
+        let test_dir = std::env::temp_dir().join(format!("buildj-test-jdk-inventory-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&test_dir);
+        fs::create_dir_all(&test_dir).unwrap();
+        test_dir.to_str().unwrap().to_string()
+    }
+
+    fn read_release(name: &str, release: &str) -> XResult<Option<JdkInfo>> {
+        let java_home = new_test_dir(name);
+        fs::write(format!("{}/{}", java_home, RELEASE_FILE), release).unwrap();
+        let jdk_info = read_release_file(&java_home);
+        fs::remove_dir_all(&java_home).unwrap();
+        jdk_info
+    }
+
+    #[test]
+    fn test_read_release_file() {
+        let jdk_info = read_release("release", "IMPLEMENTOR=\"Eclipse Adoptium\"\nIMPLEMENTOR_VERSION=\"Temurin-17.0.2+8\"\n\
+            JAVA_VERSION=\"17.0.2\"\nJAVA_RUNTIME_VERSION=\"17.0.2+8\"\nOS_ARCH=\"x86_64\"\nOS_NAME=\"Linux\"\n").unwrap().unwrap();
+        assert_eq!(jdk_info.version, "17.0.2+8");
+        assert_eq!(jdk_info.vendor.as_deref(), Some("Eclipse Adoptium"));
+        assert_eq!(jdk_info.arch.as_deref(), Some("x86_64"));
+        assert_eq!(java_vendor::identify_vendor(jdk_info.vendor.as_deref().unwrap()), Some("temurin"));
+        assert_eq!(jdk_info.get_java_version().unwrap().components, vec![17, 0, 2]);
+
+        // JDK 8 has no JAVA_RUNTIME_VERSION nor IMPLEMENTOR
+        let jdk_info = read_release("release-8", "JAVA_VERSION=\"1.8.0_242\"\nOS_ARCH=\"amd64\"\n").unwrap().unwrap();
+        assert_eq!(jdk_info.version, "1.8.0_242");
+        assert_eq!(jdk_info.vendor, None);
+        assert_eq!(jdk_info.arch.as_deref(), Some("amd64"));
+
+        assert!(read_release("release-no-version", "IMPLEMENTOR=\"Azul Systems, Inc.\"\n").is_err());
+        let java_home = new_test_dir("release-missing");
+        assert!(read_release_file(&java_home).unwrap().is_none());
+        fs::remove_dir_all(&java_home).unwrap();
+    }
+
+    #[test]
+    fn test_read_release_file_graalvm() {
+        let jdk_info = read_release("release-graalvm", "IMPLEMENTOR=\"Oracle Corporation\"\n\
+            JAVA_VERSION=\"17.0.7\"\nGRAALVM_VERSION=\"22.3.2\"\n").unwrap().unwrap();
+        assert_eq!(jdk_info.vendor.as_deref(), Some("GraalVM, Oracle Corporation"));
+        assert_eq!(java_vendor::identify_vendor(jdk_info.vendor.as_deref().unwrap()), Some("graalvm"));
+    }
+
+    #[test]
+    fn test_get_graalvm_vendor() {
+        let oracle = "Oracle Corporation".to_string();
+        let graalvm = "GraalVM Community".to_string();
+        assert_eq!(get_graalvm_vendor(false, Some(&oracle)).as_deref(), Some("Oracle Corporation"));
+        assert_eq!(get_graalvm_vendor(true, Some(&oracle)).as_deref(), Some("GraalVM, Oracle Corporation"));
+        assert_eq!(get_graalvm_vendor(true, Some(&graalvm)).as_deref(), Some("GraalVM Community"));
+        assert_eq!(get_graalvm_vendor(true, None).as_deref(), Some("GraalVM"));
+        assert_eq!(get_graalvm_vendor(false, None), None);
+    }
+
+    #[test]
+    fn test_read_jdk_info_dir_name() {
+        let test_dir = new_test_dir("dir-name");
+        for dir_name in ["jdk1.8.0_242", "jdk-17.0.2+8", "jdk-11.0.2.jdk", "zulu17"] {
+            fs::create_dir_all(format!("{}/{}", test_dir, dir_name)).unwrap();
+        }
+        let get_version = |dir_name: &str| read_jdk_info(&format!("{}/{}", test_dir, dir_name), 1).map(|jdk_info| jdk_info.version);
+        assert_eq!(get_version("jdk1.8.0_242").as_deref(), Some("1.8.0_242"));
+        assert_eq!(get_version("jdk-17.0.2+8").as_deref(), Some("17.0.2+8"));
+        assert_eq!(get_version("jdk-11.0.2.jdk").as_deref(), Some("11.0.2"));
+        assert_eq!(get_version("zulu17"), None);
+        fs::remove_dir_all(&test_dir).unwrap();
+    }
+
+    #[test]
+    fn test_jdk_inventory_save_load() {
+        let test_dir = new_test_dir("save-load");
+        let java_home = format!("{}/jdk-17", test_dir);
+        fs::create_dir_all(&java_home).unwrap();
+        fs::write(format!("{}/{}", java_home, RELEASE_FILE), "JAVA_VERSION=\"17.0.2\"\n").unwrap();
+        let mut jdk_inventory = JdkInventory::load(&format!("{}/inventory", test_dir));
+        assert_eq!(jdk_inventory.get_jdk_info(&java_home).unwrap().version, "17.0.2");
+        jdk_inventory.save();
+
+        let mut jdk_inventory = JdkInventory::load(&format!("{}/inventory", test_dir));
+        assert_eq!(jdk_inventory.jdk_infos.len(), 1);
+        assert_eq!(jdk_inventory.get_jdk_info(&java_home).unwrap().version, "17.0.2");
+        assert!(!jdk_inventory.changed);
+        fs::remove_dir_all(&test_dir).unwrap();
+    }
+}
@@ -0,0 +1,253 @@
+//! `:::list [java|maven|gradle] [--remote]`, JDKs and builders buildj can use with size, install date and source,
+//! the source is the download URL in the install manifest for tools installed by buildj, or `system`.
+//! `--remote` lists versions available in registries, see `Registry::list_tool_versions`, only file and upstream
+//! registries support listing, so the default `https://hatter.ink` lists nothing.
+//! `*` marks the one build.json resolves to.
+use std::{fs, path::Path, time::UNIX_EPOCH};
+use rust_util::{util_os, util_size};
+
+use crate::{jdk, java_vendor, local_util, registry, tool};
+use crate::java_version::{JavaVersion, JavaVersionReq};
+use crate::manifest::{self, InstallState};
+use crate::tool::BuilderName;
+use crate::misc::VERBOSE;
+
+pub const JAVA: &str = "java";
+const SOURCE_SYSTEM: &str = "system";
+const SOURCE_MACOS_JAVA_HOME: &str = "java_home";
+/// Installed by buildj before install manifest is written
+const SOURCE_UNKNOWN: &str = "unknown";
+
+pub struct InstalledTool {
+    pub version: String,
+    /// Vendor id of JDK, e.g. `temurin`
+    pub vendor: Option<String>,
+    pub home: String,
+    pub source: String,
+    /// Unix timestamp in seconds, modified time of the tool dir when not installed by buildj
+    pub installed_at: Option<u64>,
+    pub size: u64,
+}
+
+/// What build.json resolves to, `version` is the version or version range in build.json
+pub struct ResolvedTool {
+    pub version: String,
+    pub home: Option<String>,
+}
+
+pub fn print_installed_tools(tool_name: &str, resolved_tool: Option<&ResolvedTool>) {
+    let installed_tools = iff!(tool_name == JAVA, list_installed_jdks(), match BuilderName::parse(tool_name) {
+        Some(builder_name) => list_installed_builders(builder_name), None => vec![],
+    });
+    if installed_tools.is_empty() {
+        warning!("No installed {} found", tool_name);
+        return;
+    }
+    let resolved_home = resolved_tool.and_then(|resolved_tool| resolved_tool.home.as_deref()).map(get_canonical_path);
+    information!("Installed {}:", tool_name);
+    for installed_tool in &installed_tools {
+        let is_resolved = resolved_home.as_deref() == Some(get_canonical_path(&installed_tool.home).as_str());
+        success!("{} {:<16} {:<10} {:>10} {:<10} {} ({})", iff!(is_resolved, "*", " "),
+                 installed_tool.version,
+                 installed_tool.vendor.as_deref().unwrap_or("-"),
+                 format_size(installed_tool.size),
+                 installed_tool.installed_at.map(format_date).unwrap_or_else(|| "-".to_string()),
+                 installed_tool.home,
+                 installed_tool.source);
+    }
+}
+
+pub fn print_remote_tools(tool_name: &str, java_vendors: &[String], resolved_tool: Option<&ResolvedTool>) {
+    let remote_tool_names = iff!(tool_name == JAVA, jdk::get_cloud_java_names(java_vendors), vec![tool_name.to_string()]);
+    let remote_versions = match list_remote_versions(&remote_tool_names) {
+        Some(remote_versions) => remote_versions,
+        None => {
+            let registry_descriptions: Vec<String> = registry::get_registries().iter().map(|registry| registry.description()).collect();
+            warning!("No registry lists {}, --remote needs file or upstream registries (e.g. `upstream`), HTTP registries do not support listing, registries: {}",
+                     remote_tool_names.join(", "), registry_descriptions.join(", "));
+            return;
+        },
+    };
+    if remote_versions.is_empty() {
+        warning!("No available {} found in registries", remote_tool_names.join(", "));
+        return;
+    }
+    let resolved_version = resolved_tool.and_then(|resolved_tool| if tool_name == JAVA {
+        let java_version_req = JavaVersionReq::parse(&resolved_tool.version)?;
+        remote_versions.iter().rev()
+            .find(|version| JavaVersion::parse(version).map(|v| java_version_req.matches(&v)).unwrap_or(false))
+    } else {
+        remote_versions.iter().find(|version| **version == resolved_tool.version)
+    });
+    information!("Available {} ({}):", tool_name, remote_tool_names.join(", "));
+    for remote_version in &remote_versions {
+        success!("{} {}", iff!(Some(remote_version) == resolved_version, "*", " "), remote_version);
+    }
+}
+
+/// JDKs from `jdk::list_installed_jdks`, sorted by version
+fn list_installed_jdks() -> Vec<InstalledTool> {
+    let local_java_home_base_dir = match local_util::get_user_home_dir(jdk::LOCAL_JAVA_HOME_BASE_DIR) {
+        Ok(local_java_home_base_dir) => local_java_home_base_dir, Err(_) => return vec![],
+    };
+    jdk::list_installed_jdks().into_iter().map(|jdk_info| {
+        // `~/.jssp/jdks/<jdk dir>/Contents/Home`, install manifest is in `<jdk dir>`
+        let local_jdk_dir = Path::new(&jdk_info.home).strip_prefix(&local_java_home_base_dir).ok()
+            .and_then(|jdk_path| jdk_path.components().next())
+            .and_then(|jdk_dir_name| Path::new(&local_java_home_base_dir).join(jdk_dir_name).to_str().map(|d| d.to_string()));
+        let vendor = jdk_info.vendor.as_deref().map(|vendor| java_vendor::identify_vendor(vendor).unwrap_or(vendor).to_string());
+        let system_source = iff!(util_os::is_macos(), SOURCE_MACOS_JAVA_HOME, SOURCE_SYSTEM);
+        let (source, installed_at, size) = match &local_jdk_dir {
+            Some(local_jdk_dir) => get_local_install_info(local_jdk_dir),
+            None => (system_source.to_string(), get_modified_secs(&jdk_info.home), local_util::get_dir_size(&jdk_info.home)),
+        };
+        InstalledTool { version: jdk_info.version, vendor, home: jdk_info.home, source, installed_at, size }
+    }).collect()
+}
+
+/// Builders in `~/.jssp/builder/<name>-<version>`, then system builders, see `tool::list_system_builders`
+fn list_installed_builders(builder_name: BuilderName) -> Vec<InstalledTool> {
+    let mut installed_builders = vec![];
+    if let Ok(local_builder_home_base_dir) = local_util::get_user_home_dir(tool::LOCAL_BUILDER_HOME_BASE_DIR) {
+        let builder_dir_prefix = format!("{}/{}-", local_builder_home_base_dir, builder_name.name());
+        for builder_dir in local_util::list_sub_dirs(&local_builder_home_base_dir) {
+            let version = match builder_dir.strip_prefix(&builder_dir_prefix) {
+                Some(version) => version, None => continue,
+            };
+            let home = tool::get_local_builder_home_sub_first_sub_dir(&builder_dir).unwrap_or_else(|| builder_dir.clone());
+            match tool::get_builder_install_state(builder_name, &builder_dir) {
+                InstallState::Installed(install_manifest) => installed_builders.push(InstalledTool {
+                    version: install_manifest.version,
+                    vendor: None,
+                    home,
+                    source: install_manifest.url,
+                    installed_at: Some(install_manifest.installed_at).filter(|installed_at| *installed_at > 0),
+                    size: local_util::get_dir_size(&builder_dir),
+                }),
+                // adopted by writing an install manifest on the next build
+                InstallState::Legacy => installed_builders.push(InstalledTool {
+                    version: version.to_string(),
+                    vendor: None,
+                    installed_at: get_modified_secs(&home),
+                    home,
+                    source: manifest::LEGACY_SOURCE.to_string(),
+                    size: local_util::get_dir_size(&builder_dir),
+                }),
+                InstallState::Broken(reason) => warning!("Builder {} is broken: {}", builder_dir, reason),
+                InstallState::Missing => (),
+            }
+        }
+    }
+    installed_builders.sort_by_key(|installed_builder| get_version_key(&installed_builder.version));
+    for (version, home) in tool::list_system_builders(builder_name) {
+        let (installed_at, size) = (get_modified_secs(&home), local_util::get_dir_size(&home));
+        installed_builders.push(InstalledTool { version, vendor: None, home, source: SOURCE_SYSTEM.to_string(), installed_at, size });
+    }
+    installed_builders
+}
+
+fn get_local_install_info(tool_dir: &str) -> (String, Option<u64>, u64) {
+    let size = local_util::get_dir_size(tool_dir);
+    match manifest::get_install_state(tool_dir) {
+        InstallState::Installed(install_manifest) if install_manifest.installed_at > 0 => {
+            (install_manifest.url, Some(install_manifest.installed_at), size)
+        },
+        InstallState::Installed(install_manifest) => (install_manifest.url, get_modified_secs(tool_dir), size),
+        _ => (SOURCE_UNKNOWN.to_string(), get_modified_secs(tool_dir), size),
+    }
+}
+
+/// Versions of `tool_names` in all registries, sorted, registries not supporting listing are skipped,
+/// `None` when no registry supports listing `tool_names`
+fn list_remote_versions(tool_names: &[String]) -> Option<Vec<String>> {
+    let mut remote_versions: Vec<String> = vec![];
+    let mut is_listed = false;
+    for registry in registry::get_registries() {
+        for tool_name in tool_names {
+            match registry.list_tool_versions(tool_name) {
+                Ok(versions) => {
+                    remote_versions.extend(versions);
+                    is_listed = true;
+                },
+                Err(err) => if *VERBOSE {
+                    debugging!("List tool {} from registry {} failed: {}", tool_name, registry.description(), err);
+                },
+            }
+        }
+    }
+    if !is_listed {
+        return None;
+    }
+    remote_versions.sort_by_key(|version| get_version_key(version));
+    remote_versions.dedup();
+    Some(remote_versions)
+}
+
+/// `3.9.6` -> `([3, 9, 6], true)`, `8.5-rc-1` -> `([8, 5], false)`, a pre-release sorts before its release,
+/// for sorting only
+fn get_version_key(version: &str) -> (Vec<u64>, bool, String) {
+    let numbers = version.split('.')
+        .map_while(|component| component.chars().take_while(|c| c.is_ascii_digit()).collect::<String>().parse().ok())
+        .collect();
+    (numbers, !version.contains('-'), version.to_string())
+}
+
+/// `util_size::get_display_size` has no unit for bytes
+fn format_size(size: u64) -> String {
+    iff!(size < 1024, format!("{}B", size), util_size::get_display_size(size as i64))
+}
+
+fn get_modified_secs(path: &str) -> Option<u64> {
+    let modified = fs::metadata(path).ok()?.modified().ok()?;
+    Some(modified.duration_since(UNIX_EPOCH).ok()?.as_secs())
+}
+
+fn get_canonical_path(path: &str) -> String {
+    fs::canonicalize(path).ok().and_then(|p| p.to_str().map(|p| p.to_string())).unwrap_or_else(|| path.to_string())
+}
+
+/// `YYYY-MM-DD` in UTC
+fn format_date(secs: u64) -> String {
+    // days to civil date, http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = (secs / 86400) as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = iff!(mp < 10, mp + 3, mp - 9);
+    let year = yoe + era * 400 + iff!(month <= 2, 1, 0);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_version_key() {
+        let mut versions = vec!["8.5", "3.10.1", "8.5-rc-1", "3.9.6", "3.10", "8.4", "3.0.0-M1", "3.0.0", "17.0.2+8", "1.8.0_242"];
+        versions.sort_by_key(|version| get_version_key(version));
+        assert_eq!(versions, vec!["1.8.0_242", "3.0.0-M1", "3.0.0", "3.9.6", "3.10", "3.10.1", "8.4", "8.5-rc-1", "8.5", "17.0.2+8"]);
+        assert!(get_version_key("3.10") > get_version_key("3.9"));
+        assert!(get_version_key("8.5-rc-1") < get_version_key("8.5"));
+        assert!(get_version_key("8.5-rc-1") < get_version_key("8.5-rc-2"));
+    }
+
+    #[test]
+    fn test_format_date() {
+        assert_eq!(format_date(0), "1970-01-01");
+        assert_eq!(format_date(86399), "1970-01-01");
+        assert_eq!(format_date(86400), "1970-01-02");
+        // leap days
+        assert_eq!(format_date(951782400), "2000-02-29");
+        assert_eq!(format_date(951868800), "2000-03-01");
+        assert_eq!(format_date(1709164800), "2024-02-29");
+        // 2100 is not a leap year
+        assert_eq!(format_date(4107456000), "2100-02-28");
+        assert_eq!(format_date(4107542400), "2100-03-01");
+        assert_eq!(format_date(1704067199), "2023-12-31");
+        assert_eq!(format_date(1704067200), "2024-01-01");
+    }
+}
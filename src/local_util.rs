@@ -5,7 +5,9 @@ use std::fs::{self, File};
 use std::io::{Read, ErrorKind};
 use rust_util::XResult;
 use rust_util::util_io::{self, DEFAULT_BUF_SIZE, PrintStatusContext};
-use crypto::{digest::Digest, md5::Md5, sha1::Sha1, sha2::{Sha256, Sha512}};
+use crypto::{digest::Digest, md5::Md5, sha1::Sha1, sha2::{Sha256, Sha384, Sha512}};
+
+use crate::{tool, misc::ALLOW_WEAK_DIGEST};
 
 pub fn get_args_as_vec() -> Vec<String> {
     env::args().collect::<Vec<String>>()
@@ -17,34 +19,150 @@ pub fn is_buildin_args(args: &[String]) -> bool {
         .unwrap_or(false)
 }
 
-pub fn verify_file_integrity(integrity: &str, file_name: &str) -> XResult<bool> {
-    match integrity.find('-') {
-        None => simple_error!("Not supported integrigty: {}", integrity),
-        Some(index) => {
-            let digest_hex = &integrity[index+1..];
-            let calc_digest_hex = match &integrity[0..index] {
-                "sha256:hex" => calc_file_digest(&mut Sha256::new(), "SHA256", file_name)?,
-                "sha512:hex" => calc_file_digest(&mut Sha512::new(), "SHA512", file_name)?,
-                "sha1:hex" => calc_file_digest(&mut Sha1::new(), "SHA1", file_name)?,
-                "md5:hex" => calc_file_digest(&mut Md5::new(), "MD5", file_name)?,
-                _ => return simple_error!("Not supported integrigty: {}", integrity),
-            };
-            let integrity_verify_result = digest_hex == calc_digest_hex.as_str();
-            if ! integrity_verify_result {
-                failure!("Verify integrity failed, expected: {}, actual: {}", digest_hex, calc_digest_hex);
+/// Integrity is one or more whitespace separated digests, only the digests of the strongest algorithm are verified
+/// and one of them must match, same as Subresource-Integrity:
+/// * `<alg>:hex-<digest hex>`, alg is `sha256`, `sha384`, `sha512`, `sha1` or `md5`
+/// * Subresource-Integrity `<alg>-<digest base64>`, alg is `sha256`, `sha384` or `sha512`
+///
+/// MD5 and SHA1 are refused unless `BUILDJ_ALLOW_WEAK_DIGEST` or standard json#build.js#allow_weak_digest is on,
+/// weak digests are ignored when a strong digest is also given.
+pub struct IntegrityVerifier {
+    integrity: String,
+    digest_alg: String,
+    digests: Vec<IntegrityDigest>,
+}
+
+struct IntegrityDigest {
+    digest_alg: &'static str,
+    digest: Box<dyn Digest>,
+    expected_digest: Vec<u8>,
+}
+
+impl IntegrityDigest {
+    fn parse(integrity: &str) -> XResult<IntegrityDigest> {
+        let (alg, expected_digest) = if let Some(index) = integrity.find(":hex-") {
+            (&integrity[..index], decode_hex(&integrity[index + 5..])?)
+        } else if let Some(index) = integrity.find('-') {
+            // `sha256-<base64>?<options>`, options are reserved by SRI
+            let digest_base64 = integrity[index + 1..].split('?').next().unwrap_or("");
+            let alg = &integrity[..index];
+            match alg.to_lowercase().as_str() {
+                "md5" | "sha1" => return simple_error!("Not supported integrity: {}", integrity),
+                _ => (alg, base64::decode(digest_base64)?),
             }
-            Ok(integrity_verify_result)
-        },
+        } else {
+            return simple_error!("Not supported integrity: {}", integrity);
+        };
+        let (digest_alg, digest): (&'static str, Box<dyn Digest>) = match alg.to_lowercase().as_str() {
+            "sha256" => ("SHA256", Box::new(Sha256::new())),
+            "sha384" => ("SHA384", Box::new(Sha384::new())),
+            "sha512" => ("SHA512", Box::new(Sha512::new())),
+            "sha1" => ("SHA1", Box::new(Sha1::new())),
+            "md5" => ("MD5", Box::new(Md5::new())),
+            _ => return simple_error!("Not supported integrity: {}", integrity),
+        };
+        if expected_digest.len() != digest.output_bytes() {
+            return simple_error!("Integrity digest length error: {}", integrity);
+        }
+        Ok(IntegrityDigest { digest_alg, digest, expected_digest })
+    }
+
+    fn is_weak(&self) -> bool {
+        self.digest_alg == "SHA1" || self.digest_alg == "MD5"
+    }
+
+    /// Larger is stronger
+    fn get_strength(&self) -> usize {
+        ["MD5", "SHA1", "SHA256", "SHA384", "SHA512"].iter().position(|alg| *alg == self.digest_alg).unwrap_or(0)
+    }
+}
+
+impl IntegrityVerifier {
+    pub fn new(integrity: &str) -> XResult<IntegrityVerifier> {
+        let mut digests = vec![];
+        for integrity_digest in integrity.split_whitespace() {
+            digests.push(IntegrityDigest::parse(integrity_digest)?);
+        }
+        if digests.is_empty() {
+            return simple_error!("Integrity is empty");
+        }
+        if digests.iter().any(|d| !d.is_weak()) {
+            digests.retain(|d| !d.is_weak());
+        } else if !is_weak_digest_allowed() {
+            return simple_error!("Weak digest is refused: {}, set BUILDJ_ALLOW_WEAK_DIGEST=true to allow", integrity);
+        } else {
+            warning!("Weak digest is allowed: {}", integrity);
+        }
+        let strongest = digests.iter().map(|d| d.get_strength()).max().unwrap_or(0);
+        digests.retain(|d| d.get_strength() == strongest);
+        Ok(IntegrityVerifier {
+            integrity: integrity.to_string(),
+            digest_alg: digests[0].digest_alg.to_string(),
+            digests,
+        })
+    }
+
+    pub fn input(&mut self, buf: &[u8]) {
+        for integrity_digest in &mut self.digests {
+            integrity_digest.digest.input(buf);
+        }
+    }
+
+    pub fn input_file(&mut self, file_name: &str) -> XResult<()> {
+        let digest_alg = self.digest_alg.clone();
+        read_file_with_status(&digest_alg, file_name, |buf| self.input(buf))
+    }
+
+    /// One digest of the strongest algorithm matches
+    pub fn verify(&mut self) -> bool {
+        let mut calc_digest = vec![];
+        for integrity_digest in &mut self.digests {
+            calc_digest = vec![0_u8; integrity_digest.digest.output_bytes()];
+            integrity_digest.digest.result(&mut calc_digest);
+            if calc_digest == integrity_digest.expected_digest {
+                return true;
+            }
+        }
+        let expected_digests = self.digests.iter().map(|d| encode_hex(&d.expected_digest)).collect::<Vec<_>>();
+        failure!("Verify integrity {} failed, expected: {}, actual: {}", self.digest_alg, expected_digests.join(" or "),
+                 encode_hex(&calc_digest));
+        false
+    }
+
+    pub fn get_integrity(&self) -> &str {
+        &self.integrity
     }
 }
 
+fn is_weak_digest_allowed() -> bool {
+    if *ALLOW_WEAK_DIGEST {
+        return true;
+    }
+    tool::read_standard_config_object()
+        .map(|standard_config_object| standard_config_object["build.js"]["allow_weak_digest"].as_bool().unwrap_or(false))
+        .unwrap_or(false)
+}
+
+fn decode_hex(digest_hex: &str) -> XResult<Vec<u8>> {
+    if digest_hex.len() % 2 == 1 || !digest_hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return simple_error!("Digest is not hex: {}", digest_hex);
+    }
+    Ok((0..digest_hex.len()).step_by(2)
+        .map(|i| u8::from_str_radix(&digest_hex[i..i + 2], 16).unwrap_or(0))
+        .collect())
+}
+
+fn encode_hex(digest: &[u8]) -> String {
+    digest.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
 pub fn calc_sha256(d: &[u8]) -> String {
     let mut sha256 = Sha256::new();
     sha256.input(d);
     sha256.result_str()
 }
 
-pub fn calc_file_digest(digest: &mut dyn Digest, digest_alg: &str, file_name: &str) -> XResult<String> {
+fn read_file_with_status<F: FnMut(&[u8])>(digest_alg: &str, file_name: &str, mut input: F) -> XResult<()> {
     let mut buf = [0u8; DEFAULT_BUF_SIZE];
     let mut f = File::open(file_name)?;
     let file_len = f.metadata().map(|md| md.len() as i64).unwrap_or(-1_i64);
@@ -52,12 +170,12 @@ pub fn calc_file_digest(digest: &mut dyn Digest, digest_alg: &str, file_name: &s
     let mut written = 0_i64;
     loop {
         let len = match f.read(&mut buf) {
-            Ok(0) => { println!(); return Ok(digest.result_str()); },
+            Ok(0) => { println!(); return Ok(()); },
             Ok(len) => len,
             Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
             Err(e) => return Err(Box::new(e)),
         };
-        digest.input(&buf[..len]);
+        input(&buf[..len]);
         written += len as i64;
         util_io::print_status_last_line(&format!("Calc {}", digest_alg), file_len, written, &mut print_status_context);
     }
@@ -82,22 +200,68 @@ pub fn is_path_exists(dir: &str, sub_dir: &str) -> bool {
     Path::new(full_path).exists()
 }
 
-pub fn run_command_and_wait(cmd: &mut Command) -> XResult<()> {
-    cmd.spawn()?.wait()?;
-    Ok(())
+/// Non hidden sub dirs, sorted, empty when `dir` not exists
+pub fn list_sub_dirs(dir: &str) -> Vec<String> {
+    let paths = match fs::read_dir(Path::new(dir)) {
+        Ok(paths) => paths, Err(_) => return vec![],
+    };
+    let mut sub_dirs = vec![];
+    for dir_entry in paths.flatten() {
+        let is_hidden = dir_entry.file_name().to_str().map(|n| n.starts_with('.')).unwrap_or(true);
+        if is_hidden || !dir_entry.path().is_dir() {
+            continue;
+        }
+        if let Some(p) = dir_entry.path().to_str() {
+            sub_dirs.push(p.to_string());
+        }
+    }
+    sub_dirs.sort();
+    sub_dirs
+}
+
+/// Total size of files in `dir`, symlinks are not followed
+pub fn get_dir_size(dir: &str) -> u64 {
+    let mut size = 0;
+    let mut dirs = vec![Path::new(dir).to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let paths = match fs::read_dir(&dir) {
+            Ok(paths) => paths, Err(_) => continue,
+        };
+        for dir_entry in paths.flatten() {
+            match dir_entry.metadata() {
+                Ok(metadata) if metadata.is_dir() => dirs.push(dir_entry.path()),
+                Ok(metadata) => size += metadata.len(),
+                Err(_) => (),
+            }
+        }
+    }
+    size
 }
 
-pub fn extract_package_and_wait(dir: &str, file_name: &str) -> XResult<()> {
-    let mut cmd: Command;
-    if file_name.ends_with(".zip") {
-        cmd = Command::new("unzip");
-    } else if file_name.ends_with(".tar.gz") {
-        cmd = Command::new("tar");
-        cmd.arg("-xzvf");
-    } else {
-        return simple_error!("Unknown file type: {}", file_name);
+/// Write to `<file_name>.<pid>.tmp` then rename, readers never see a partially written file
+pub fn write_file_atomic(file_name: &str, content: &str) -> XResult<()> {
+    if let Some(dir) = Path::new(file_name).parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let tmp_file_name = format!("{}.{}.tmp", file_name, std::process::id());
+    if let Err(err) = fs::write(&tmp_file_name, content).and_then(|_| fs::rename(&tmp_file_name, file_name)) {
+        fs::remove_file(&tmp_file_name).ok();
+        return Err(err.into());
     }
-    cmd.arg(file_name).current_dir(dir).spawn()?.wait()?;
+    Ok(())
+}
+
+/// Find executable `bin_name` in `PATH`, symlinks are resolved, e.g. `/usr/bin/mvn` -> `/usr/share/maven/bin/mvn`
+pub fn find_in_path(bin_name: &str) -> Option<String> {
+    let path = env::var_os("PATH")?;
+    env::split_paths(&path).map(|dir| dir.join(bin_name))
+        .find(|bin| bin.is_file())
+        .and_then(|bin| fs::canonicalize(bin).ok())
+        .and_then(|bin| bin.to_str().map(|b| b.to_string()))
+}
+
+pub fn run_command_and_wait(cmd: &mut Command) -> XResult<()> {
+    cmd.spawn()?.wait()?;
     Ok(())
 }
 
@@ -114,3 +278,68 @@ pub fn init_dir(dir: &str) {
         });
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ABC_SHA256_HEX: &str = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+    const ABC_SHA256_BASE64: &str = "ungWv48Bz+pBQUDeXa4iI7ADYaOWF3qctBD/YfIAFa0=";
+
+    fn get_abc_sha512() -> Vec<u8> {
+        let mut sha512 = Sha512::new();
+        sha512.input(b"abc");
+        let mut digest = vec![0_u8; sha512.output_bytes()];
+        sha512.result(&mut digest);
+        digest
+    }
+
+    fn verify_abc(integrity: &str) -> bool {
+        let mut integrity_verifier = IntegrityVerifier::new(integrity).unwrap();
+        integrity_verifier.input(b"abc");
+        integrity_verifier.verify()
+    }
+
+    #[test]
+    fn test_parse_integrity() {
+        assert!(verify_abc(&format!("sha256:hex-{}", ABC_SHA256_HEX)));
+        assert!(verify_abc(&format!("SHA256:hex-{}", ABC_SHA256_HEX.to_uppercase())));
+        assert!(verify_abc(&format!("sha256-{}", ABC_SHA256_BASE64)));
+        // SRI options are ignored
+        assert!(verify_abc(&format!("sha256-{}?opt", ABC_SHA256_BASE64)));
+        assert!(!verify_abc(&format!("sha256:hex-{}", "0".repeat(64))));
+        assert!(IntegrityVerifier::new("").is_err());
+        assert!(IntegrityVerifier::new("sha256:hex-abc").is_err());
+        assert!(IntegrityVerifier::new("sha256:hex-00").is_err());
+        assert!(IntegrityVerifier::new("sha1-AAAAAAAAAAAAAAAAAAAAAAAAAAA=").is_err());
+        assert!(IntegrityVerifier::new("md5-AAAAAAAAAAAAAAAAAAAAAA==").is_err());
+        // SRI never allows md5 and sha1, whatever the case and the weak digest policy
+        assert!(IntegrityDigest::parse("SHA1-AAAAAAAAAAAAAAAAAAAAAAAAAAA=").is_err());
+        assert!(IntegrityDigest::parse("Md5-AAAAAAAAAAAAAAAAAAAAAA==").is_err());
+        assert!(IntegrityDigest::parse(&format!("SHA256-{}", ABC_SHA256_BASE64)).is_ok());
+        assert!(IntegrityVerifier::new("sha3-AAAA").is_err());
+        assert!(IntegrityVerifier::new(&format!("sha256:hex-{}", "0".repeat(62))).is_err());
+    }
+
+    #[test]
+    fn test_verify_strongest_integrity() {
+        let abc_sha512_base64 = base64::encode(get_abc_sha512());
+        let wrong_sha512_base64 = base64::encode(vec![0_u8; 64]);
+        // only sha512 is checked when present
+        assert!(verify_abc(&format!("sha256:hex-{} sha512-{}", "0".repeat(64), abc_sha512_base64)));
+        assert!(!verify_abc(&format!("sha256-{} sha512-{}", ABC_SHA256_BASE64, wrong_sha512_base64)));
+        // one of the strongest digests matches
+        assert!(verify_abc(&format!("sha512-{} sha512-{}", wrong_sha512_base64, abc_sha512_base64)));
+        assert!(!verify_abc(&format!("sha512-{} sha512-{}", wrong_sha512_base64, wrong_sha512_base64)));
+        // weak digest is ignored when a strong digest is given
+        assert!(verify_abc(&format!("md5:hex-{} sha256:hex-{}", "0".repeat(32), ABC_SHA256_HEX)));
+    }
+
+    #[test]
+    fn test_decode_hex() {
+        assert_eq!(decode_hex("00ff1A").unwrap(), vec![0, 255, 26]);
+        assert!(decode_hex("0").is_err());
+        assert!(decode_hex("zz").is_err());
+        assert_eq!(encode_hex(&[0, 255, 26]), "00ff1a");
+    }
+}
@@ -0,0 +1,53 @@
+//! Cross-process install lock, so parallel buildj runs install a tool only once.
+use std::thread;
+use std::fs::{File, OpenOptions};
+use std::time::{Duration, Instant};
+use fs2::FileExt;
+use rust_util::XResult;
+
+use crate::{local_util, misc::{VERBOSE, LOCK_TIMEOUT_SECS}};
+
+const LOCK_FILE_PREFIX: &str = ".lock-";
+const LOCK_RETRY_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Exclusive lock on `<base_dir>/.lock-<name>`, released on drop
+pub struct InstallLock {
+    lock_file_name: String,
+    lock_file: File,
+}
+
+impl InstallLock {
+    pub fn acquire(base_dir: &str, name: &str) -> XResult<InstallLock> {
+        local_util::init_dir(base_dir);
+        let lock_file_name = format!("{}/{}{}", base_dir, LOCK_FILE_PREFIX, name);
+        let lock_file = OpenOptions::new().create(true).truncate(false).write(true).open(&lock_file_name)?;
+        let start = Instant::now();
+        let timeout = Duration::from_secs(*LOCK_TIMEOUT_SECS);
+        let mut waiting_printed = false;
+        while lock_file.try_lock_exclusive().is_err() {
+            if !waiting_printed {
+                information!("Waiting for another buildj process installing {}, lock: {}", name, lock_file_name);
+                waiting_printed = true;
+            }
+            if start.elapsed() > timeout {
+                return simple_error!("Wait lock timeout after {}s, lock: {}, set BUILDJ_LOCK_TIMEOUT to change",
+                                     timeout.as_secs(), lock_file_name);
+            }
+            thread::sleep(LOCK_RETRY_INTERVAL);
+        }
+        if *VERBOSE {
+            debugging!("Acquired lock: {}", lock_file_name);
+        }
+        Ok(InstallLock { lock_file_name, lock_file })
+    }
+}
+
+impl Drop for InstallLock {
+    fn drop(&mut self) {
+        if let Err(err) = self.lock_file.unlock() {
+            warning!("Release lock: {} failed: {}", self.lock_file_name, err);
+        } else if *VERBOSE {
+            debugging!("Released lock: {}", self.lock_file_name);
+        }
+    }
+}
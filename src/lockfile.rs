@@ -0,0 +1,374 @@
+//! Project lockfile `build.lock.json`, written next to build.json, pins the exact JDK and builder
+//! packages resolved from build.json, so every machine builds with the same toolchain:
+//! ```text
+//! {
+//!     "java": [{
+//!         "platform": "linux-x86_64",
+//!         "requested": "1.8",
+//!         "name": "jdk-linux",
+//!         "version": "1.8.0_242",
+//!         "file_name": "jdk-8u242-linux-x64.tar.gz",
+//!         "url": "https://...",
+//!         "integrity": "sha256:hex-0123..."
+//!     }, { "platform": "macos-aarch64", ... }],
+//!     "builder": { "requested": "3.5.2", "name": "maven", "version": "3.5.2", ... }
+//! }
+//! ```
+//! JDK packages are OS and arch specific, one entry per platform, the entry of the current platform is added on the first run.
+//! An entry whose `requested` no longer matches build.json is ignored, run `buildj :::lock update` to refresh.
+use std::{env, fs, path::Path};
+use json::JsonValue;
+use rust_util::XResult;
+
+use crate::local_util;
+use crate::build_json::{BuildJson, BUILD_JSON};
+use crate::manifest::{self, InstallManifest, InstallState};
+use crate::registry::ToolPackageDetail;
+use crate::misc::{JAVA_VERSION, BUILDER_VERSION};
+
+pub const BUILD_LOCK_JSON: &str = "build.lock.json";
+
+#[derive(Clone, Debug)]
+pub struct LockedTool {
+    /// `<os>-<arch>` of OS and arch specific packages, e.g. `linux-x86_64`, `None` for builders
+    pub platform: Option<String>,
+    /// Version in build.json, e.g. `1.8`, with java vendors, e.g. `17@graalvm,temurin`
+    pub requested: String,
+    /// Tool name, e.g. `jdk-linux`, `maven`
+    pub name: String,
+    /// Exact tool version, e.g. `1.8.0_242`
+    pub version: String,
+    pub file_name: String,
+    pub url: String,
+    pub integrity: String,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct BuildLock {
+    /// JDKs of all platforms
+    pub java: Vec<LockedTool>,
+    pub builder: Option<LockedTool>,
+    /// An entry is dropped because it does not match build.json
+    pub is_stale: bool,
+}
+
+/// `<os>-<arch>` of the current platform, e.g. `linux-x86_64`, `macos-aarch64`
+pub fn get_current_platform() -> String {
+    format!("{}-{}", env::consts::OS, env::consts::ARCH)
+}
+
+impl LockedTool {
+    pub fn from_manifest(requested: &str, install_manifest: &InstallManifest, platform: Option<String>) -> LockedTool {
+        LockedTool {
+            platform,
+            requested: requested.to_string(),
+            name: install_manifest.name.clone(),
+            version: install_manifest.version.clone(),
+            file_name: install_manifest.file_name.clone(),
+            url: install_manifest.url.clone(),
+            integrity: install_manifest.integrity.clone(),
+        }
+    }
+
+    /// Resolved from registry by `:::lock update`
+    pub fn from_tool_package_detail(requested: &str, tool_package_detail: &ToolPackageDetail, platform: Option<String>) -> LockedTool {
+        LockedTool {
+            platform,
+            requested: requested.to_string(),
+            name: tool_package_detail.n.clone(),
+            version: tool_package_detail.v.clone(),
+            file_name: tool_package_detail.name.clone(),
+            url: tool_package_detail.url.clone(),
+            integrity: tool_package_detail.integrity.clone(),
+        }
+    }
+
+    /// Installed package is the locked one
+    pub fn matches(&self, install_manifest: &InstallManifest) -> bool {
+        self.name == install_manifest.name && self.version == install_manifest.version
+            && self.integrity == install_manifest.integrity
+    }
+
+    pub fn to_tool_package_detail(&self) -> ToolPackageDetail {
+        ToolPackageDetail {
+            url: self.url.clone(),
+            integrity: self.integrity.clone(),
+            name: self.file_name.clone(),
+            n: self.name.clone(),
+            v: self.version.clone(),
+            size: None,
+            signature_url: None,
+        }
+    }
+
+    fn parse(path: &str, locked_tool_object: &JsonValue) -> XResult<LockedTool> {
+        let get_str = |key: &str| -> XResult<String> {
+            match locked_tool_object[key].as_str() {
+                Some(value) => Ok(value.to_string()),
+                None => simple_error!("{}#{}#{} is not assigned", BUILD_LOCK_JSON, path, key),
+            }
+        };
+        Ok(LockedTool {
+            platform: locked_tool_object["platform"].as_str().map(|p| p.to_string()),
+            requested: get_str("requested")?,
+            name: get_str("name")?,
+            version: get_str("version")?,
+            file_name: get_str("file_name")?,
+            url: get_str("url")?,
+            integrity: get_str("integrity")?,
+        })
+    }
+
+    fn to_json(&self) -> JsonValue {
+        let mut locked_tool_object = JsonValue::new_object();
+        if let Some(platform) = &self.platform {
+            locked_tool_object["platform"] = platform.as_str().into();
+        }
+        locked_tool_object["requested"] = self.requested.as_str().into();
+        locked_tool_object["name"] = self.name.as_str().into();
+        locked_tool_object["version"] = self.version.as_str().into();
+        locked_tool_object["file_name"] = self.file_name.as_str().into();
+        locked_tool_object["url"] = self.url.as_str().into();
+        locked_tool_object["integrity"] = self.integrity.as_str().into();
+        locked_tool_object
+    }
+}
+
+impl BuildLock {
+    /// JDK of the current platform
+    pub fn get_java(&self) -> Option<&LockedTool> {
+        let current_platform = get_current_platform();
+        self.java.iter().find(|java| java.platform.as_deref() == Some(current_platform.as_str()))
+    }
+
+    pub fn read_from(build_lock_file: &str) -> XResult<BuildLock> {
+        let build_lock_object = json::parse(&fs::read_to_string(build_lock_file)?)?;
+        let java_object = &build_lock_object["java"];
+        let mut java = vec![];
+        if java_object.is_array() {
+            for (i, locked_java_object) in java_object.members().enumerate() {
+                java.push(LockedTool::parse(&format!("java#{}", i), locked_java_object)?);
+            }
+        } else if !java_object.is_null() {
+            // lockfile without platform, written by older buildj
+            java.push(LockedTool::parse("java", java_object)?);
+        }
+        let builder_object = &build_lock_object["builder"];
+        Ok(BuildLock {
+            java,
+            builder: iff!(builder_object.is_null(), None, Some(LockedTool::parse("builder", builder_object)?)),
+            is_stale: false,
+        })
+    }
+
+    pub fn write_to(&self, build_lock_file: &str) -> XResult<()> {
+        let mut build_lock_object = JsonValue::new_object();
+        if !self.java.is_empty() {
+            build_lock_object["java"] = JsonValue::Array(self.java.iter().map(|java| java.to_json()).collect());
+        }
+        if let Some(builder) = &self.builder {
+            build_lock_object["builder"] = builder.to_json();
+        }
+        // written on normal builds, concurrent builds must not leave a truncated lockfile
+        local_util::write_file_atomic(build_lock_file, &json::stringify_pretty(build_lock_object, 4))
+    }
+}
+
+/// `build.lock.json` next to build.json in `project_dir`, `None` when build.json is assigned by env or inferred
+pub fn get_build_lock_file(project_dir: &str) -> Option<String> {
+    if (*JAVA_VERSION).is_some() || (*BUILDER_VERSION).is_some() {
+        return None;
+    }
+    let project_dir = Path::new(project_dir);
+    if !project_dir.join(BUILD_JSON).is_file() {
+        return None;
+    }
+    Some(project_dir.join(BUILD_LOCK_JSON).to_str()?.to_string())
+}
+
+/// Read lockfile and drop entries not matching build.json, `None` when lockfile not exists
+pub fn read_build_lock(build_json: &BuildJson) -> Option<BuildLock> {
+    let build_lock_file = get_build_lock_file(&build_json.project_dir)?;
+    if !Path::new(&build_lock_file).exists() {
+        return None;
+    }
+    let mut build_lock = match BuildLock::read_from(&build_lock_file) {
+        Ok(build_lock) => build_lock, Err(err) => {
+            warning!("Read {} failed: {}, ignore it", build_lock_file, err);
+            return None;
+        },
+    };
+    success!("Find {} @ {}", BUILD_LOCK_JSON, build_lock_file);
+    let java_requested = build_json.get_java_requested();
+    let java_count = build_lock.java.len();
+    build_lock.java.retain(|java| java_requested.as_ref() == Some(&java.requested) && java.platform.is_some());
+    if build_lock.java.len() != java_count {
+        warning!("{}#java is stale or has no platform, run `buildj :::lock update` to update", BUILD_LOCK_JSON);
+        build_lock.is_stale = true;
+    }
+    if let Some(builder) = &build_lock.builder {
+        let is_builder_matched = build_json.builder.as_ref()
+            .map(|b| b.name == builder.name && b.version == builder.requested).unwrap_or(false);
+        if !is_builder_matched {
+            warning!("{}#builder is stale, requested: {} {}, run `buildj :::lock update` to update",
+                     BUILD_LOCK_JSON, builder.name, builder.requested);
+            build_lock.builder = None;
+            build_lock.is_stale = true;
+        }
+    }
+    Some(build_lock)
+}
+
+/// Lock the resolved JDK and builder homes, only packages installed by buildj (with install manifest) can be locked,
+/// JDKs of other platforms in `build_lock` are kept
+pub fn write_build_lock(build_lock_file: &str, build_lock: Option<&BuildLock>, build_json: &BuildJson, java_home: &str, builder_home: &str) -> XResult<()> {
+    let current_platform = get_current_platform();
+    let mut new_build_lock = BuildLock::default();
+    if let Some(build_lock) = build_lock {
+        new_build_lock.java.extend(build_lock.java.iter()
+            .filter(|java| java.platform.as_deref() != Some(current_platform.as_str())).cloned());
+    }
+    match (build_json.get_java_requested(), find_install_manifest(java_home)) {
+        (Some(java), Some(install_manifest)) => {
+            new_build_lock.java.push(LockedTool::from_manifest(&java, &install_manifest, Some(current_platform)));
+        },
+        _ => warning!("Java home: {} is not installed by buildj (e.g. system or macOS java_home JDK), java is not locked", java_home),
+    }
+    match (&build_json.builder, find_install_manifest(builder_home)) {
+        (Some(builder), Some(install_manifest)) => {
+            new_build_lock.builder = Some(LockedTool::from_manifest(&builder.version, &install_manifest, None));
+        },
+        _ => warning!("Builder home: {} is not installed by buildj (e.g. system builder), builder is not locked", builder_home),
+    }
+    new_build_lock.write_to(build_lock_file)?;
+    success!("Write file success: {}", build_lock_file);
+    Ok(())
+}
+
+/// Installed by buildj, with install manifest of a known package
+pub fn is_lockable(home: &str) -> bool {
+    find_install_manifest(home).is_some()
+}
+
+/// Install manifest is in the home dir, e.g. JDK, or its parent dir, e.g. `maven-3.5.2/apache-maven-3.5.2`,
+/// or `jdk-11/Contents/Home` on macOS
+fn find_install_manifest(home: &str) -> Option<InstallManifest> {
+    for dir in Path::new(home).ancestors().take(3) {
+        if let InstallState::Installed(install_manifest) = manifest::get_install_state(dir.to_str()?) {
+            // the package of a legacy install is unknown
+            return iff!(install_manifest.is_legacy(), None, Some(install_manifest));
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::build_json::BuildJsonBuilder;
+
+    fn new_locked_tool(platform: Option<&str>, requested: &str, name: &str, version: &str) -> LockedTool {
+        LockedTool {
+            platform: platform.map(|p| p.to_string()),
+            requested: requested.to_string(),
+            name: name.to_string(),
+            version: version.to_string(),
+            file_name: format!("{}-{}.tar.gz", name, version),
+            url: format!("https://example.com/{}-{}.tar.gz", name, version),
+            integrity: format!("sha256:hex-{}", version),
+        }
+    }
+
+    fn new_build_json(project_dir: &str, java: &str, builder_version: &str) -> BuildJson {
+        BuildJson {
+            java: Some(java.to_string()),
+            builder: Some(BuildJsonBuilder { name: "maven".to_string(), version: builder_version.to_string() }),
+            project_dir: project_dir.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn new_project_dir(name: &str, build_lock: &BuildLock) -> String {
+        let test_dir = std::env::temp_dir().join(format!("buildj-test-lockfile-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&test_dir);
+        fs::create_dir_all(&test_dir).unwrap();
+        fs::write(test_dir.join(BUILD_JSON), "{}").unwrap();
+        build_lock.write_to(test_dir.join(BUILD_LOCK_JSON).to_str().unwrap()).unwrap();
+        test_dir.to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn test_build_lock_round_trip() {
+        let current_platform = get_current_platform();
+        let build_lock = BuildLock {
+            java: vec![
+                new_locked_tool(Some("other-platform"), "17", "jdk-other", "17.0.1"),
+                new_locked_tool(Some(&current_platform), "17", "jdk-linux", "17.0.2"),
+            ],
+            builder: Some(new_locked_tool(None, "3.5.2", "maven", "3.5.2")),
+            is_stale: false,
+        };
+        let project_dir = new_project_dir("round-trip", &build_lock);
+        let read_build_lock = BuildLock::read_from(&get_build_lock_file(&project_dir).unwrap()).unwrap();
+        assert_eq!(read_build_lock.java.len(), 2);
+        for (java, read_java) in build_lock.java.iter().zip(read_build_lock.java.iter()) {
+            assert_eq!(java.to_json(), read_java.to_json());
+        }
+        assert_eq!(read_build_lock.get_java().unwrap().version, "17.0.2");
+        let read_builder = read_build_lock.builder.unwrap();
+        assert_eq!(read_builder.platform, None);
+        assert_eq!(read_builder.to_json(), build_lock.builder.unwrap().to_json());
+        fs::remove_dir_all(&project_dir).unwrap();
+    }
+
+    #[test]
+    fn test_build_lock_without_platform() {
+        let project_dir = new_project_dir("without-platform", &BuildLock::default());
+        let build_lock_file = format!("{}/{}", project_dir, BUILD_LOCK_JSON);
+        // written by older buildj
+        let locked_java_object = new_locked_tool(None, "17", "jdk-linux", "17.0.2").to_json();
+        fs::write(&build_lock_file, json::stringify(object! { "java" => locked_java_object })).unwrap();
+        let build_lock = BuildLock::read_from(&build_lock_file).unwrap();
+        assert_eq!(build_lock.java.len(), 1);
+        assert!(build_lock.get_java().is_none());
+        let build_lock = read_build_lock(&new_build_json(&project_dir, "17", "3.5.2")).unwrap();
+        assert!(build_lock.is_stale);
+        assert!(build_lock.java.is_empty());
+        fs::remove_dir_all(&project_dir).unwrap();
+    }
+
+    #[test]
+    fn test_read_build_lock_stale() {
+        let current_platform = get_current_platform();
+        let build_lock = BuildLock {
+            java: vec![
+                new_locked_tool(Some("other-platform"), "17", "jdk-other", "17.0.1"),
+                new_locked_tool(Some(&current_platform), "17", "jdk-linux", "17.0.2"),
+            ],
+            builder: Some(new_locked_tool(None, "3.5.2", "maven", "3.5.2")),
+            is_stale: false,
+        };
+        let project_dir = new_project_dir("stale", &build_lock);
+
+        let build_lock = read_build_lock(&new_build_json(&project_dir, "17", "3.5.2")).unwrap();
+        assert!(!build_lock.is_stale);
+        assert_eq!(build_lock.java.len(), 2);
+        assert!(build_lock.builder.is_some());
+
+        let build_lock = read_build_lock(&new_build_json(&project_dir, "21", "3.5.2")).unwrap();
+        assert!(build_lock.is_stale);
+        assert!(build_lock.java.is_empty());
+        assert!(build_lock.builder.is_some());
+
+        let build_lock = read_build_lock(&new_build_json(&project_dir, "17", "3.9.6")).unwrap();
+        assert!(build_lock.is_stale);
+        assert_eq!(build_lock.java.len(), 2);
+        assert!(build_lock.builder.is_none());
+
+        // inferred project without build.json is never locked
+        fs::remove_file(format!("{}/{}", project_dir, BUILD_JSON)).unwrap();
+        assert!(get_build_lock_file(&project_dir).is_none());
+        assert!(read_build_lock(&new_build_json(&project_dir, "17", "3.5.2")).is_none());
+        fs::remove_dir_all(&project_dir).unwrap();
+    }
+}
@@ -3,25 +3,42 @@
 #[macro_use] extern crate rust_util;
 
 use std::fs;
-use std::collections::HashMap;
+use std::path::Path;
+use std::collections::{BTreeMap, HashMap};
 use std::process::{self, Command};
 
 pub mod jdk;
+pub mod java_version;
+pub mod java_vendor;
+pub mod java_version_file;
+pub mod infer;
+pub mod toolchains;
+pub mod list;
+pub mod jdk_inventory;
+pub mod archive;
 pub mod local_util;
 pub mod http;
 pub mod tool;
 pub mod build_json;
 pub mod misc;
+pub mod registry;
+pub mod manifest;
+pub mod lock;
+pub mod lockfile;
+pub mod upstream;
+pub mod signature;
+pub mod wrapper;
 
 use rust_util::util_cmd;
 use tool::*;
 use jdk::*;
 use build_json::*;
 use misc::*;
+use lockfile::{BuildLock, LockedTool};
 
 
 fn do_with_buildin_arg_java_cmd(first_arg: &str, args: &[String]) {
-    let first_num_pos = first_arg.chars().position(|c| c >= '0' && c <= '9');
+    let first_num_pos = first_arg.chars().position(|c| c.is_ascii_digit());
     let (cmd, ver) = match first_num_pos {
         None => {
             failure!("Java command version is not assigned!");
@@ -31,7 +48,7 @@ fn do_with_buildin_arg_java_cmd(first_arg: &str, args: &[String]) {
             (&first_arg[3..pos], &first_arg[pos..])
         },
     };
-    match get_java_home(ver) {
+    match get_java_home(ver, &[]) {
         None => failure!("Assigned java version not found: {}", ver),
         Some(java_home) => {
             success!("Find java home: {}", java_home);
@@ -54,18 +71,10 @@ fn do_with_buildin_arg_java_cmd(first_arg: &str, args: &[String]) {
     };
 }
 
-fn do_with_buildin_arg_maven(first_arg: &str, args: &[String]) {
-    do_with_buildin_arg_builder(first_arg, args, "maven")
-}
-
-fn do_with_buildin_arg_gradle(first_arg: &str, args: &[String]) {
-    do_with_buildin_arg_builder(first_arg, args, "gradle")
-}
-
 fn do_with_buildin_arg_config(_first_arg: &str, args: &[String]) {
     information!("Current config file: ~/{}", tool::STANDARD_CONFIG_JSON);
     if args.len() <= 2 {
-        failure!("No arguments, get or set.");
+        failure!("No arguments, get, set or registries.");
         return;
     }
     match args[2].as_str() {
@@ -83,6 +92,9 @@ fn do_with_buildin_arg_config(_first_arg: &str, args: &[String]) {
                 }
             }
         },
+        "registries" => for registry_config in registry::get_registry_configs() {
+            success!("Registry: {}{}", registry_config.url, iff!(registry_config.auth_token.is_some(), " (with auth token)", ""));
+        },
         arg => failure!("Unknown argument: {}", arg)
     }
 }
@@ -99,7 +111,7 @@ fn do_with_buildin_arg_builder(first_arg: &str, args: &[String], builder_name: &
         has_java = true;
         let java_version = &args[2][6..];
         if !java_version.is_empty() {
-            java_home = match get_java_home(java_version) {
+            java_home = match get_java_home(java_version, &[]) {
                 Some(h) => h, None => {
                     failure!("Assigned java version not found: {}", java_version);
                     return;
@@ -127,7 +139,7 @@ fn do_with_buildin_arg_builder(first_arg: &str, args: &[String], builder_name: &
     cmd.envs(&new_env);
     let from_index = iff!(has_java, 3, 2);
     for arg in args.iter().skip(from_index) {
-        cmd.arg(&arg);
+        cmd.arg(arg);
     }
     if let Err(err) = util_cmd::run_command_and_wait(&mut cmd) {
         failure!("Run build command failed: {}", err);
@@ -135,38 +147,204 @@ fn do_with_buildin_arg_builder(first_arg: &str, args: &[String], builder_name: &
 }
 
 fn do_with_buildin_arg_ddd(first_arg: &str, args: &[String]) {
-    let build_json_object = match read_build_json_object() {
-        Some(object) => object, None => return,
+    let build_json = match read_build_json_object() {
+        Some(build_json) => build_json, None => return,
     };
-    let build_json_object_xrun = &build_json_object["xRuns"][&first_arg[3..]];
-    if build_json_object_xrun.is_null() {
-        failure!("Cannot find build.json#xRuns#{}", &first_arg[3..]);
-        return;
-    }
-    let cmd_name = build_json_object_xrun[0].to_string();
-    let mut cmd = Command::new(&cmd_name);
+    let build_json_xrun = match build_json.x_runs.get(&first_arg[3..]) {
+        Some(xrun) => xrun, None => {
+            failure!("Cannot find build.json#xRuns#{}", &first_arg[3..]);
+            return;
+        },
+    };
+    let cmd_name = &build_json_xrun[0];
+    let mut cmd = Command::new(cmd_name);
     cmd.current_dir(".");
     let mut cmd_args = vec![];
-    for i in 1..build_json_object_xrun.len() {
+    for xrun_arg in build_json_xrun.iter().skip(1) {
         if *VERBOSE {
-            cmd_args.push(build_json_object_xrun[i].to_string());
+            cmd_args.push(xrun_arg.to_string());
         }
-        cmd.arg(build_json_object_xrun[i].to_string());
+        cmd.arg(xrun_arg);
     }
     for arg in args.iter().skip(3) {
         if *VERBOSE {
             cmd_args.push(arg.to_string());
         }
-        cmd.arg(arg.to_string());
+        cmd.arg(arg);
     }
     if *VERBOSE {
-        debugging!("Running cmd: {}, args: {:?}", &cmd_name, cmd_args);
+        debugging!("Running cmd: {}, args: {:?}", cmd_name, cmd_args);
     }
     if let Err(err) = util_cmd::run_command_and_wait(&mut cmd) {
         failure!("Run xRun command failed: {}", err);
     }
 }
 
+fn do_with_buildin_arg_check(args: &[String]) {
+    let download = args.iter().skip(2).any(|arg| arg == "--download");
+    if ! check_build_json(args, download) {
+        process::exit(1);
+    }
+}
+
+fn check_build_json(args: &[String], download: bool) -> bool {
+    let build_json = match read_build_json_object() {
+        Some(build_json) => build_json, None => return false,
+    };
+    success!("{} is valid", BUILD_JSON);
+    let (java_version, builder) = match (&build_json.java, &build_json.builder) {
+        (Some(java_version), Some(builder)) => (java_version, builder),
+        (None, _) => {
+            failure!("Java version is not assigned!");
+            return false;
+        },
+        (_, None) => {
+            failure!("Builder name or version is not assigned!");
+            return false;
+        },
+    };
+
+    let build_lock = lockfile::read_build_lock(&build_json);
+    let java_home = resolve_java_home(java_version, &build_json.java_vendors, build_lock.as_ref(), download);
+    let java_vendors_message = get_vendors_message(&build_json.java_vendors);
+    match &java_home {
+        Some(java_home) => success!("Java {}{} resolved: {}", java_version, java_vendors_message, java_home),
+        None => failure!("Java {}{} not installed{}", java_version, java_vendors_message, iff!(download, "", ", use --download to install")),
+    }
+    let builder_desc = resolve_builder_desc(builder, build_lock.as_ref(), download);
+    match &builder_desc {
+        Some(builder_desc) => success!("Builder {} {} resolved: {}", builder.name, builder.version, builder_desc.home),
+        None => failure!("Builder {} {} not installed{}", builder.name, builder.version, iff!(download, "", ", use --download to install")),
+    }
+    let (java_home, builder_desc) = match (java_home, builder_desc) {
+        (Some(java_home), Some(builder_desc)) => (java_home, builder_desc),
+        _ => return false,
+    };
+
+    // same as `buildj ARGS`, skip `:::check` and `--download`
+    let build_args: Vec<String> = args.iter().enumerate()
+        .filter(|(i, arg)| *i != 1 && *arg != "--download")
+        .map(|(_, arg)| arg.to_string())
+        .collect();
+    let final_args = match get_final_args(&build_args, &build_json) {
+        Some(fa) => add_toolchains_args(fa, &builder_desc, &build_json), None => return false,
+    };
+    information!("Command: {} {}", builder_desc.get_builder_bin(), final_args.join(" "));
+
+    let old_env: BTreeMap<String, String> = get_env().into_iter().collect();
+    let new_env: BTreeMap<String, String> = get_build_env(&java_home, &builder_desc, &build_json).into_iter().collect();
+    information!("Environment changes:");
+    for (k, v) in &new_env {
+        match old_env.get(k) {
+            None => information!("  + {}={}", k, v),
+            Some(old_v) if old_v != v => information!("  ~ {}={}", k, v),
+            Some(_) => (),
+        }
+    }
+    for k in old_env.keys() {
+        if !new_env.contains_key(k) {
+            information!("  - {}", k);
+        }
+    }
+    true
+}
+
+fn do_with_buildin_arg_lock(args: &[String]) {
+    if args.get(2).map(|arg| arg.as_str()) != Some("update") {
+        failure!("Unknown arguments, use :::lock update");
+        return;
+    }
+    let build_json = match read_build_json_object() {
+        Some(build_json) => build_json, None => return,
+    };
+    let build_lock_file = match lockfile::get_build_lock_file(&build_json.project_dir) {
+        Some(build_lock_file) => build_lock_file, None => {
+            failure!("Cannot lock build.json assigned by env");
+            return;
+        },
+    };
+    // ignore the current lockfile, resolve the newest packages from registries, then from build.json
+    let build_lock = lockfile::read_build_lock(&build_json);
+    let registry_build_lock = resolve_registry_build_lock(&build_json);
+    let (java_home, builder_desc) = match resolve_java_and_builder(&build_json, Some(&registry_build_lock)) {
+        Some((java_home, builder_desc)) => (java_home, builder_desc), None => return,
+    };
+    if let Err(err) = lockfile::write_build_lock(&build_lock_file, build_lock.as_ref(), &build_json, &java_home, &builder_desc.home) {
+        failure!("Write {} failed: {}", lockfile::BUILD_LOCK_JSON, err);
+    }
+}
+
+/// Packages of build.json in registries, missing entries are resolved from local installs
+fn resolve_registry_build_lock(build_json: &BuildJson) -> BuildLock {
+    let mut build_lock = BuildLock::default();
+    if let (Some(java_version), Some(java_requested)) = (&build_json.java, build_json.get_java_requested()) {
+        let java_package = get_cloud_java_names(&build_json.java_vendors).iter()
+            .find_map(|java_name| tool::get_tool_package_detail(java_name, java_version).ok());
+        match java_package {
+            Some(java_package) => build_lock.java.push(LockedTool::from_tool_package_detail(
+                &java_requested, &java_package, Some(lockfile::get_current_platform()))),
+            None => warning!("Java {}{} not found in registries, lock the installed one", java_version, get_vendors_message(&build_json.java_vendors)),
+        }
+    }
+    if let Some(builder) = &build_json.builder {
+        match tool::get_tool_package_detail(&builder.name, &builder.version) {
+            Ok(builder_package) if builder_package.v == builder.version => {
+                build_lock.builder = Some(LockedTool::from_tool_package_detail(&builder.version, &builder_package, None));
+            },
+            _ => warning!("Builder {} {} not found in registries, lock the installed one", builder.name, builder.version),
+        }
+    }
+    build_lock
+}
+
+fn do_with_buildin_arg_toolchains(args: &[String]) {
+    let toolchains_file = match args.get(2) {
+        Some(toolchains_file) => toolchains_file.to_string(),
+        None => match toolchains::get_maven_toolchains_file() {
+            Ok(toolchains_file) => toolchains_file, Err(err) => {
+                failure!("Get toolchains.xml failed: {}", err);
+                return;
+            },
+        },
+    };
+    match toolchains::write_toolchains_file(&toolchains_file) {
+        Ok(jdk_count) => success!("Write {} JDK(s) to: {}", jdk_count, toolchains_file),
+        Err(err) => failure!("Write toolchains: {} failed: {}", toolchains_file, err),
+    }
+}
+
+fn do_with_buildin_arg_list(args: &[String]) {
+    let remote = args.iter().skip(2).any(|arg| arg == "--remote");
+    let tool_names: Vec<&str> = args.iter().skip(2).filter(|arg| *arg != "--remote").map(|arg| arg.as_str()).collect();
+    if let Some(tool_name) = tool_names.iter().find(|tool_name| **tool_name != list::JAVA && BuilderName::parse(tool_name).is_none()) {
+        failure!("Unknown tool: {}, supported: {}, {}", tool_name, list::JAVA, BuilderName::supported_names());
+        return;
+    }
+    let tool_names = iff!(tool_names.is_empty(), vec![list::JAVA, BuilderName::Maven.name(), BuilderName::Gradle.name()], tool_names);
+
+    // build.json is optional, only for marking what it resolves to, tools are never downloaded
+    let has_build_json = (*JAVA_VERSION).is_some() || (*BUILDER_VERSION).is_some()
+        || find_build_json_in_current().is_some() || find_build_json_in_parents().is_some();
+    let build_json = iff!(has_build_json, read_build_json_object(), None);
+    let build_lock = build_json.as_ref().and_then(lockfile::read_build_lock);
+    let java_vendors = build_json.as_ref().map(|build_json| build_json.java_vendors.clone()).unwrap_or_default();
+    for tool_name in tool_names {
+        let resolved_tool = build_json.as_ref().and_then(|build_json| if tool_name == list::JAVA {
+            let java_version = build_json.java.as_ref()?;
+            let home = resolve_java_home(java_version, &build_json.java_vendors, build_lock.as_ref(), false);
+            Some(list::ResolvedTool { version: java_version.clone(), home })
+        } else {
+            let builder = build_json.builder.as_ref().filter(|builder| builder.name == tool_name)?;
+            let home = resolve_builder_desc(builder, build_lock.as_ref(), false).map(|builder_desc| builder_desc.home);
+            Some(list::ResolvedTool { version: builder.version.clone(), home })
+        });
+        list::print_installed_tools(tool_name, resolved_tool.as_ref());
+        if remote {
+            list::print_remote_tools(tool_name, &java_vendors, resolved_tool.as_ref());
+        }
+    }
+}
+
 fn do_with_buildin_args(args: &[String]) {
     let first_arg = args.get(1).unwrap();
     match first_arg.as_str() {
@@ -174,6 +352,10 @@ fn do_with_buildin_args(args: &[String]) {
         ":::version"      => print_version(),
         ":::create"       => create_build_json(args),
         ":::config"       => do_with_buildin_arg_config(first_arg, args),
+        ":::check"        => do_with_buildin_arg_check(args),
+        ":::lock"         => do_with_buildin_arg_lock(args),
+        ":::toolchains"   => do_with_buildin_arg_toolchains(args),
+        ":::list"         => do_with_buildin_arg_list(args),
         a if a.starts_with(":::jar")    => do_with_buildin_arg_java_cmd(a, args),
         a if a.starts_with(":::java")   => do_with_buildin_arg_java_cmd(a, args),
         a if a.starts_with(":::jinfo")  => do_with_buildin_arg_java_cmd(a, args),
@@ -188,70 +370,99 @@ fn do_with_buildin_args(args: &[String]) {
         a if a.starts_with(":::jstack") => do_with_buildin_arg_java_cmd(a, args),
         a if a.starts_with(":::jstat")  => do_with_buildin_arg_java_cmd(a, args),
         a if a.starts_with(":::jimage") => do_with_buildin_arg_java_cmd(a, args),
-        a if a.starts_with(":::maven")  => do_with_buildin_arg_maven   (a, args),
-        a if a.starts_with(":::gradle") => do_with_buildin_arg_gradle  (a, args),
         a if a.starts_with("...")       => do_with_buildin_arg_ddd     (a, args),
-        _ => failure!("Unknown args: {:?}", &args),
+        a => match BuilderName::all().iter().find(|b| a.starts_with(&format!(":::{}", b.name()))) {
+            Some(builder_name) => do_with_buildin_arg_builder(a, args, builder_name.name()),
+            None => failure!("Unknown args: {:?}", &args),
+        },
     }
 }
 
-fn get_java_and_builder(build_json_object: &json::JsonValue) -> Option<(String, BuilderDesc)> {
-    let java_version_j = &build_json_object["java"];
-    let builder_name_j = &build_json_object["builder"]["name"];
-    let builder_version_j = &build_json_object["builder"]["version"];
+/// Resolve with `build.lock.json`, lockfile is created when not exists,
+/// JDK of the current platform is added when the lockfile has no stale entry
+fn get_java_and_builder(build_json: &BuildJson) -> Option<(String, BuilderDesc)> {
+    let build_lock = lockfile::read_build_lock(build_json);
+    let (java_home, builder_desc) = resolve_java_and_builder(build_json, build_lock.as_ref())?;
+    let is_lock_missing = match &build_lock {
+        None => true,
+        Some(build_lock) => !build_lock.is_stale && build_lock.get_java().is_none() && lockfile::is_lockable(&java_home),
+    };
+    if let Some(build_lock_file) = lockfile::get_build_lock_file(&build_json.project_dir).filter(|_| is_lock_missing) {
+        if build_lock.is_some() || !Path::new(&build_lock_file).exists() {
+            if let Err(err) = lockfile::write_build_lock(&build_lock_file, build_lock.as_ref(), build_json, &java_home, &builder_desc.home) {
+                warning!("Write {} failed: {}", lockfile::BUILD_LOCK_JSON, err);
+            }
+        }
+    }
+    Some((java_home, builder_desc))
+}
 
-    if java_version_j.is_null() {
-        failure!("Java version is not assigned!");
-        return None;
+fn resolve_java_home(java_version: &str, java_vendors: &[String], build_lock: Option<&BuildLock>, download: bool) -> Option<String> {
+    match build_lock.and_then(|build_lock| build_lock.get_java()) {
+        Some(locked_java) => get_locked_java_home(locked_java, download),
+        None => iff!(download, get_java_home(java_version, java_vendors), get_installed_java_home(java_version, java_vendors)),
     }
-    if builder_name_j.is_null() || builder_version_j.is_null() {
-        failure!("Builder name or version is not assigned!");
-        return None;
+}
+
+fn resolve_builder_desc(builder: &BuildJsonBuilder, build_lock: Option<&BuildLock>, download: bool) -> Option<BuilderDesc> {
+    match build_lock.and_then(|build_lock| build_lock.builder.as_ref()) {
+        Some(locked_builder) => tool::get_locked_builder_home(locked_builder, download),
+        None => iff!(download,
+            tool::get_builder_home(&builder.name, &builder.version),
+            tool::get_local_builder_home(&builder.name, &builder.version)),
     }
-    let java_version = java_version_j.as_str().unwrap();
-    let builder_name = builder_name_j.as_str().unwrap();
-    let builder_version = builder_version_j.as_str().unwrap();
+}
+
+fn resolve_java_and_builder(build_json: &BuildJson, build_lock: Option<&BuildLock>) -> Option<(String, BuilderDesc)> {
+    let java_version = match &build_json.java {
+        Some(java_version) => java_version, None => {
+            failure!("Java version is not assigned!");
+            return None;
+        },
+    };
+    let builder = match &build_json.builder {
+        Some(builder) => builder, None => {
+            failure!("Builder name or version is not assigned!");
+            return None;
+        },
+    };
     if *VERBOSE {
         debugging!("Java version: {}", java_version);
-        debugging!("Builder name: {}", builder_name);
-        debugging!("Builder version: {}", builder_version);
+        debugging!("Java vendors: {:?}", build_json.java_vendors);
+        debugging!("Builder name: {}", builder.name);
+        debugging!("Builder version: {}", builder.version);
     }
 
-    let java_home = match get_java_home(java_version) {
+    let java_home = match resolve_java_home(java_version, &build_json.java_vendors, build_lock, true) {
         Some(h) => h, None => {
-            failure!("Assigned java version not found: {}", java_version);
+            failure!("Assigned java version not found: {}{}", java_version, get_vendors_message(&build_json.java_vendors));
             return None;
         },
     };
-    let builder_desc = match tool::get_builder_home(builder_name, builder_version) {
+    let builder_desc = match resolve_builder_desc(builder, build_lock, true) {
         Some(h) => h, None => {
-            failure!("Assigned builder: {}, version: {} not found.", builder_name, builder_version);
+            failure!("Assigned builder: {}, version: {} not found.", builder.name, builder.version);
             return None;
         },
     };
     Some((java_home, builder_desc))
 }
 
-fn get_final_args(args: &[String], build_json_object: &json::JsonValue) -> Option<Vec<String>> {
+fn get_final_args(args: &[String], build_json: &BuildJson) -> Option<Vec<String>> {
     let mut final_args:Vec<String> = vec![];
     if args.len() > 1 {
         let arg1 = &args[1];
-        if arg1.starts_with("::") {
-            let a_cmd = &arg1[2..];
-            let a_cmd_j = &build_json_object["xArgs"][a_cmd];
-            if a_cmd_j.is_null() {
-                warning!("xArgs argument not found: {}", a_cmd);
-                if args.len() == 2 {
-                    failure!("Only one xArgs argument, exit.");
-                    return None;
-                }
-                final_args.push(arg1.to_string());
-            } else {
-                for a_j in a_cmd_j.members() {
-                    if ! a_j.is_null() {
-                        final_args.push(a_j.as_str().unwrap().to_string());
+        if let Some(a_cmd) = arg1.strip_prefix("::") {
+            match build_json.x_args.get(a_cmd) {
+                None => {
+                    warning!("xArgs argument not found: {}", a_cmd);
+                    if args.len() == 2 {
+                        failure!("Only one xArgs argument, exit.");
+                        return None;
                     }
-                }
+                    final_args.push(arg1.to_string());
+                },
+                Some(x_args) => final_args.extend(x_args.iter().cloned()),
             }
         } else {
             final_args.push(arg1.to_string());
@@ -265,69 +476,147 @@ fn get_final_args(args: &[String], build_json_object: &json::JsonValue) -> Optio
     Some(final_args)
 }
 
-fn process_envs(the_env: &mut HashMap<String, String>, build_json_object: &json::JsonValue) {
-    let envs_j = &build_json_object["envs"];
-    if ! envs_j.is_null() {
-        for env in envs_j.members() {
-            if *VERBOSE {
-                debugging!("Env: {}", env);
-            }
-            let (env_k, env_v) = (&env[0], &env[1]);
-            if let (Some(env_k_str), Some(env_v_str)) = (env_k.as_str(), env_v.as_str()) {
-                the_env.insert(env_k_str.to_owned(), env_v_str.to_owned());
-            }
+/// `--global-toolchains ~/.jssp/toolchains/<project>.xml` for maven, `-Porg.gradle.java.installations.paths=...` for gradle,
+/// when build.json `toolchains` is true
+fn add_toolchains_args(final_args: Vec<String>, builder_desc: &BuilderDesc, build_json: &BuildJson) -> Vec<String> {
+    match builder_desc.name {
+        BuilderName::Maven if build_json.toolchains == Some(true) => add_maven_toolchains_args(final_args, build_json),
+        BuilderName::Gradle if build_json.toolchains == Some(true) => add_gradle_toolchains_args(final_args, build_json),
+        _ => final_args,
+    }
+}
+
+fn add_maven_toolchains_args(final_args: Vec<String>, build_json: &BuildJson) -> Vec<String> {
+    if final_args.iter().any(|arg| arg == "--global-toolchains" || arg == "-gt") {
+        return final_args;
+    }
+    match toolchains::write_project_toolchains_file(&build_json.project_dir) {
+        Ok(toolchains_file) => {
+            let mut toolchains_args = vec!["--global-toolchains".to_string(), toolchains_file];
+            toolchains_args.extend(final_args);
+            toolchains_args
+        },
+        Err(err) => {
+            warning!("Write toolchains failed: {}", err);
+            final_args
+        },
+    }
+}
+
+/// JDK of the gradle toolchain in the project is installed first
+fn add_gradle_toolchains_args(final_args: Vec<String>, build_json: &BuildJson) -> Vec<String> {
+    let installations_paths_prefix = format!("-P{}=", toolchains::GRADLE_INSTALLATIONS_PATHS);
+    if final_args.iter().any(|arg| arg.starts_with(&installations_paths_prefix)) {
+        return final_args;
+    }
+    toolchains::provision_gradle_toolchain(&build_json.project_dir);
+    match toolchains::get_gradle_installations_paths_arg() {
+        Some(installations_paths_arg) => {
+            let mut toolchains_args = vec![installations_paths_arg];
+            toolchains_args.extend(final_args);
+            toolchains_args
+        },
+        None => final_args,
+    }
+}
+
+fn get_build_env(java_home: &str, builder_desc: &BuilderDesc, build_json: &BuildJson) -> HashMap<String, String> {
+    let mut new_env = get_env_with_java_home(java_home);
+    for builder_home_name in builder_desc.get_builder_home_name() {
+        new_env.insert(builder_home_name, builder_desc.home.clone());
+    }
+    process_envs(&mut new_env, build_json);
+    new_env
+}
+
+fn process_envs(the_env: &mut HashMap<String, String>, build_json: &BuildJson) {
+    for (env_k, env_v) in &build_json.envs {
+        if *VERBOSE {
+            debugging!("Env: {}={}", env_k, env_v);
         }
+        the_env.insert(env_k.to_owned(), env_v.to_owned());
     }
 }
 
-fn read_build_json_object_from_env() -> Option<json::JsonValue> {
+fn read_build_json_object_from_env() -> Option<BuildJson> {
     if (*JAVA_VERSION).is_some() || (*BUILDER_VERSION).is_some() {
-        let mut build_json_object = object!{};
-        if (*JAVA_VERSION).is_some() {
-            build_json_object["java"] = (*JAVA_VERSION).as_ref().unwrap().to_string().into();
-        }
-        if (*BUILDER_VERSION).is_some() {
-            let builder_version = (*BUILDER_VERSION).as_ref().unwrap().to_string();
-            if builder_version.starts_with("gradle") {
-                build_json_object["builder"] = object! {
-                    "name" => "gradle",
-                    "version" => builder_version[6..],
-                };
-            } else if builder_version.starts_with("maven") {
-                build_json_object["builder"] = object! {
-                    "name" => "maven",
-                    "version" => builder_version[5..],
-                };
+        let mut build_json = BuildJson {
+            java: (*JAVA_VERSION).clone(),
+            ..Default::default()
+        };
+        if let Some(builder_version) = &*BUILDER_VERSION {
+            let builder_name_and_version = BuilderName::all().iter().find_map(|b| {
+                builder_version.strip_prefix(b.name()).map(|version| (b.name(), version))
+            });
+            if let Some((name, version)) = builder_name_and_version {
+                build_json.builder = Some(BuildJsonBuilder { name: name.into(), version: version.into() });
+            } else if builder_version == wrapper::BUILDER_WRAPPER {
+                build_json.builder = Some(BuildJsonBuilder { name: wrapper::BUILDER_WRAPPER.into(), version: "".into() });
             } else {
                 warning!("Unknown builder: {}", builder_version);
             }
         }
         if *VERBOSE {
-            debugging!("Use env configed build.json: {}",  json::stringify(build_json_object.clone()));
+            debugging!("Use env configed build.json: {:?}", build_json);
         }
         success!("Find build.json @ENV");
-        Some(build_json_object)
+        Some(build_json)
     } else {
         None
     }
 }
 
-fn read_build_json_object() -> Option<json::JsonValue> {
-    if let Some(o) = read_build_json_object_from_env() {
-        return Some(o);
+fn read_build_json_object() -> Option<BuildJson> {
+    let (mut build_json, project_dir) = match read_build_json_object_from_env() {
+        Some(o) => (o, ".".to_string()),
+        None => match find_build_json() {
+            Some(build_json_file) => {
+                let project_dir = Path::new(&build_json_file).parent()?.to_str()?.to_string();
+                (read_build_json_object_from_file(&build_json_file)?, project_dir)
+            },
+            None => match infer::infer_build_json() {
+                Some(build_json_and_project_dir) => build_json_and_project_dir,
+                None => {
+                    failure!("Cannot find {}, pom.xml, build.gradle or build.xml", BUILD_JSON);
+                    return None;
+                },
+            },
+        },
+    };
+    build_json.project_dir = project_dir.clone();
+    registry::set_project_registries(&build_json.registries);
+    if let Err(err) = wrapper::resolve_wrapper_builder(&mut build_json, &project_dir) {
+        failure!("Resolve wrapper builder failed: {}", err);
+        return None;
+    }
+    if let Err(err) = java_version_file::resolve_java_version_file(&mut build_json, &project_dir) {
+        failure!("Resolve java version file failed: {}", err);
+        return None;
     }
+    Some(build_json)
+}
 
-    let build_json = find_build_json()?;
+fn read_build_json_object_from_file(build_json: &str) -> Option<BuildJson> {
     success!("Find {} @ {}", BUILD_JSON, build_json);
 
     let build_json_content = fs::read_to_string(build_json).map_err(|err| {
         failure!("Read {} failed: {}", BUILD_JSON, err);
         err
     }).ok()?;
-    json::parse(&build_json_content).map_err(|err| {
+    let build_json_object = json::parse(&build_json_content).map_err(|err| {
         failure!("Parse JSON failed: {}", err);
         err
-    }).ok()
+    }).ok()?;
+    match BuildJson::parse(&build_json_object) {
+        Ok(build_json) => Some(build_json),
+        Err(errs) => {
+            failure!("Invalid {}, {} error(s) found:", BUILD_JSON, errs.len());
+            for err in &errs {
+                failure!("  {}", err);
+            }
+            None
+        },
+    }
 }
 
 
@@ -360,28 +649,24 @@ fn main() {
     }
     local_util::init_home_dir(jdk::LOCAL_JAVA_HOME_BASE_DIR);
 
-    let build_json_object = match read_build_json_object() {
-        Some(object) => object, None => return,
+    let build_json = match read_build_json_object() {
+        Some(build_json) => build_json, None => return,
     };
 
-    let (java_home, builder_desc) = match get_java_and_builder(&build_json_object) {
+    let (java_home, builder_desc) = match get_java_and_builder(&build_json) {
         Some((java_home, builder_desc)) => (java_home, builder_desc), None => return,
     };
    
     success!("JAVA_HOME    = {}", java_home);
     success!("BUILDER_HOME = {}", &builder_desc.home);
 
-    let mut new_env = get_env_with_java_home(&java_home);
-    for builder_home_name in builder_desc.get_builder_home_name() {
-        new_env.insert(builder_home_name, builder_desc.home.clone());
-    }
-    process_envs(&mut new_env, &build_json_object);
+    let new_env = get_build_env(&java_home, &builder_desc, &build_json);
 
     let mut cmd = Command::new(builder_desc.get_builder_bin());
     cmd.envs(&new_env);
 
-    let final_args = match get_final_args(&args, &build_json_object) {
-        Some(fa) => fa, None => return,
+    let final_args = match get_final_args(&args, &build_json) {
+        Some(fa) => add_toolchains_args(fa, &builder_desc, &build_json), None => return,
     };
     if *VERBOSE {
         debugging!("Final arguments: {:?}", &final_args);
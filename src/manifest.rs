@@ -0,0 +1,104 @@
+//! Install manifest, written into a tool dir after the package is downloaded, verified and
+//! extracted in a staging dir and renamed into place, a tool dir without a valid manifest is broken,
+//! except builders installed before install manifests, see `tool::adopt_legacy_builder`.
+use std::{fs, path::Path};
+use rust_util::{XResult, util_time};
+
+use crate::registry::ToolPackageDetail;
+
+pub const INSTALL_MANIFEST_JSON: &str = ".buildj_install.json";
+pub const STAGING_DIR_PREFIX: &str = ".staging-";
+/// `url` of the manifest of a tool installed before install manifests, see `InstallManifest::new_legacy`
+pub const LEGACY_SOURCE: &str = "legacy";
+
+#[derive(Clone, Debug)]
+pub struct InstallManifest {
+    pub name: String,
+    pub version: String,
+    pub url: String,
+    pub integrity: String,
+    pub file_name: String,
+    /// Unix timestamp in seconds
+    pub installed_at: u64,
+}
+
+pub enum InstallState {
+    Missing,
+    Installed(InstallManifest),
+    Broken(String),
+    /// Builder installed before install manifests, only from `tool::get_builder_install_state`
+    Legacy,
+}
+
+impl InstallManifest {
+    pub fn new(tool_package_detail: &ToolPackageDetail) -> InstallManifest {
+        InstallManifest {
+            name: tool_package_detail.n.clone(),
+            version: tool_package_detail.v.clone(),
+            url: tool_package_detail.url.clone(),
+            integrity: tool_package_detail.integrity.clone(),
+            file_name: tool_package_detail.name.clone(),
+            installed_at: util_time::get_current_secs(),
+        }
+    }
+
+    /// Tool installed by buildj before install manifests, the package is unknown
+    pub fn new_legacy(name: &str, version: &str) -> InstallManifest {
+        InstallManifest {
+            name: name.to_string(),
+            version: version.to_string(),
+            url: LEGACY_SOURCE.to_string(),
+            integrity: String::new(),
+            file_name: String::new(),
+            installed_at: util_time::get_current_secs(),
+        }
+    }
+
+    pub fn is_legacy(&self) -> bool {
+        self.url == LEGACY_SOURCE
+    }
+
+    pub fn write_to(&self, dir: &str) -> XResult<()> {
+        let manifest_object = object! {
+            "name" => self.name.as_str(),
+            "version" => self.version.as_str(),
+            "url" => self.url.as_str(),
+            "integrity" => self.integrity.as_str(),
+            "file_name" => self.file_name.as_str(),
+            "installed_at" => self.installed_at,
+        };
+        fs::write(format!("{}/{}", dir, INSTALL_MANIFEST_JSON), json::stringify_pretty(manifest_object, 4))?;
+        Ok(())
+    }
+
+    pub fn read_from(dir: &str) -> XResult<InstallManifest> {
+        let manifest_object = json::parse(&fs::read_to_string(format!("{}/{}", dir, INSTALL_MANIFEST_JSON))?)?;
+        let get_str = |key: &str| -> XResult<String> {
+            match manifest_object[key].as_str() {
+                Some(value) => Ok(value.to_string()),
+                None => simple_error!("Install manifest {} is not assigned in: {}", key, dir),
+            }
+        };
+        Ok(InstallManifest {
+            name: get_str("name")?,
+            version: get_str("version")?,
+            url: get_str("url")?,
+            integrity: get_str("integrity")?,
+            file_name: get_str("file_name")?,
+            installed_at: manifest_object["installed_at"].as_u64().unwrap_or(0),
+        })
+    }
+}
+
+pub fn get_install_state(dir: &str) -> InstallState {
+    if !Path::new(dir).exists() {
+        return InstallState::Missing;
+    }
+    if !Path::new(&format!("{}/{}", dir, INSTALL_MANIFEST_JSON)).exists() {
+        return InstallState::Broken(format!("install manifest {} not found", INSTALL_MANIFEST_JSON));
+    }
+    match InstallManifest::read_from(dir) {
+        Ok(install_manifest) => InstallState::Installed(install_manifest),
+        Err(err) => InstallState::Broken(format!("read install manifest failed: {}", err)),
+    }
+}
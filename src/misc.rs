@@ -5,16 +5,21 @@ pub const BUILDJ:     &str = "buildj";
 pub const BUDERJ_VER: &str = env!("CARGO_PKG_VERSION");
 pub const BUILD_DATE: &str = env!("BUILD_DATE");
 const     GIT_HASH:   &str = env!("GIT_HASH");
+const DEFAULT_LOCK_TIMEOUT_SECS: u64 = 30 * 60;
 
 
 lazy_static! {
     pub static ref VERBOSE: bool   = util_env::is_env_on("BUILDJ_VERBOSE");
     pub static ref NOAUTH: bool    = util_env::is_env_on("BUILDJ_NOAUTH");
     pub static ref NOBUILDIN: bool = util_env::is_env_on("BUILDJ_NOBUILDIN");
+    pub static ref ALLOW_WEAK_DIGEST: bool = util_env::is_env_on("BUILDJ_ALLOW_WEAK_DIGEST");
     pub static ref AUTH_TOKEN: Option<String>      = env::var("BUILDJ_AUTH_TOKEN").ok();
     pub static ref JAVA_VERSION: Option<String>    = env::var("BUILDJ_JAVA").ok();
     pub static ref BUILDER_VERSION: Option<String> = env::var("BUILDJ_BUILDER").ok();
     pub static ref BUILD_YEAR: String              = env::var("BUILD_YEAR").unwrap_or_else(|_| "unknown".to_string());
+    /// Seconds to wait for another buildj process installing the same package
+    pub static ref LOCK_TIMEOUT_SECS: u64 = env::var("BUILDJ_LOCK_TIMEOUT")
+        .ok().and_then(|t| t.parse().ok()).unwrap_or(DEFAULT_LOCK_TIMEOUT_SECS);
 }
 
 pub fn print_usage() {
@@ -0,0 +1,470 @@
+//! Tool registry, resolves tool name and version to a downloadable package.
+//!
+//! HTTP registry protocol, `<base_url>` e.g. `https://hatter.ink`:
+//! ```text
+//! GET <base_url>/tool/query_tool_by_name_version.json?__auth_token=<token>&name=<name>&ver=<version>
+//! GET <base_url>/tool/query_tool_by_name_version_without_auth.json?name=<name>&ver=<version>
+//! {
+//!     "status": 200,
+//!     "message": "<error message when status is not 200>",
+//!     "data": {
+//!         "url": "<package download URL>",
+//!         "integrity": "<alg>:hex-<digest> or SRI <alg>-<base64>, space separated, e.g. sha256:hex-0123... sha512-AbC...",
+//!         "name": "<package file name>, e.g. apache-maven-3.5.2-bin.tar.gz",
+//!         "n": "<tool name>, e.g. maven",
+//!         "v": "<tool version>, e.g. 3.5.2",
+//!         "size": <optional package size in bytes, download is aborted when exceeded>,
+//!         "signature_url": "<optional minisign signature URL, defaults to <url>.minisig>"
+//!     }
+//! }
+//!
+//! GET <base_url>/repo/archive_info_version.json?gid=<group id>&aid=<artifact id>
+//! {"status": 200, "data": "<latest version>"}
+//! ```
+//!
+//! File registry, `file:///mnt/buildj` or `/mnt/buildj`, reads `<dir>/index.json` (or the assigned
+//! `.json` file), `url` is optional, absolute or relative to `<dir>`, defaults to `file://<dir>/<name>`,
+//! `signature_url` is resolved the same way:
+//! ```text
+//! {
+//!     "tools": [
+//!         {"n": "maven", "v": "3.5.2", "name": "apache-maven-3.5.2-bin.tar.gz", "integrity": "sha256:hex-0123...", "size": 8799579}
+//!     ]
+//! }
+//! ```
+//!
+//! A JDK version in file registries matches exactly, otherwise the highest version in the range or with the prefix,
+//! e.g. `1.8`, `>=11`, `~17.0.3`. HTTP registries get a JDK version or a feature version, e.g. `17.x` is sent as `17`,
+//! other ranges are rejected. Builder versions are never ranges, e.g. `sbt 1.9.7` is not Java `9.7`.
+//!
+//! Versions for `:::list --remote` are the `tools` in `index.json` of file registries, HTTP registries do not support listing.
+//!
+//! Upstream registries, `maven+<url>`, `gradle+<url>`, `ant+<url>`, `sbt+<url>`, `adoptium+<url>` or `upstream`, see `upstream.rs`.
+//!
+//! Registries are tried in order: build.json `registries`, then `~/.standard_config.json`
+//! `build.js.registries`, `https://hatter.ink` is used only when none is configured.
+use std::{fs, path::Path, sync::Mutex};
+use json::JsonValue;
+use rust_util::XResult;
+
+use crate::{http, tool, upstream, misc::{VERBOSE, NOAUTH}};
+use crate::java_version::{JavaVersion, JavaVersionReq};
+
+pub const DEFAULT_REGISTRY_URL: &str = "https://hatter.ink";
+
+const TOOL_PACKAGE_DETAIL_PATH: &str = "/tool/query_tool_by_name_version.json";
+const TOOL_PACKAGE_DETAIL_PATH_WITHOUT_AUTH: &str = "/tool/query_tool_by_name_version_without_auth.json";
+const GET_ARCHIVER_VERSION_PATH: &str = "/repo/archive_info_version.json";
+const FILE_URL_PREFIX: &str = "file://";
+const FILE_REGISTRY_INDEX_JSON: &str = "index.json";
+
+lazy_static! {
+    static ref PROJECT_REGISTRIES: Mutex<Vec<RegistryConfig>> = Mutex::new(vec![]);
+}
+
+/// Registry in build.json or user config, `"https://mirror"` or `{"url": "https://mirror", "auth_token": "..."}`
+#[derive(Clone, Debug)]
+pub struct RegistryConfig {
+    pub url: String,
+    pub auth_token: Option<String>,
+}
+
+impl RegistryConfig {
+    pub fn parse(registry_object: &JsonValue) -> XResult<RegistryConfig> {
+        if let Some(url) = registry_object.as_str() {
+            return Ok(RegistryConfig { url: url.to_string(), auth_token: None });
+        }
+        let url = match registry_object["url"].as_str() {
+            Some(url) => url, None => return simple_error!("Registry url is not assigned: {}", registry_object),
+        };
+        let auth_token = &registry_object["auth_token"];
+        if !auth_token.is_null() && !auth_token.is_string() {
+            return simple_error!("Registry auth_token is not string: {}", registry_object);
+        }
+        Ok(RegistryConfig { url: url.to_string(), auth_token: auth_token.as_str().map(|t| t.to_string()) })
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ToolPackageDetail {
+    pub url: String,
+    pub integrity: String,
+    /// Package file name
+    pub name: String,
+    /// Tool name
+    pub n: String,
+    /// Tool version
+    pub v: String,
+    /// Package size in bytes, when advertised by registry
+    pub size: Option<u64>,
+    /// Detached signature URL, see `signature.rs`
+    pub signature_url: Option<String>,
+}
+
+impl ToolPackageDetail {
+    pub fn parse(tool_package_detail: &str) -> XResult<ToolPackageDetail> {
+        let detail_object = json::parse(tool_package_detail)?;
+        if detail_object["status"] != 200 {
+            return simple_error!("Error in get tool package detail: {}", detail_object["message"]);
+        }
+        let data = &detail_object["data"];
+        let (url, integrity, name) = match (data["url"].as_str(), data["integrity"].as_str(), data["name"].as_str()) {
+            (Some(url), Some(integrity), Some(name)) => (url, integrity, name),
+            _ => return simple_error!("Parse tool package detail failed: {}", tool_package_detail),
+        };
+        Ok(ToolPackageDetail {
+            url: url.to_string(),
+            integrity: integrity.to_string(),
+            name: name.to_string(),
+            n: data["n"].to_string(),
+            v: data["v"].to_string(),
+            size: data["size"].as_u64(),
+            signature_url: data["signature_url"].as_str().map(|u| u.to_string()),
+        })
+    }
+}
+
+pub trait Registry {
+    fn description(&self) -> String;
+
+    fn get_tool_package_detail(&self, name: &str, version: &str) -> XResult<ToolPackageDetail>;
+
+    fn get_archive_version(&self, _gid: &str, _aid: &str) -> XResult<String> {
+        simple_error!("Get archive version is not supported by registry: {}", self.description())
+    }
+
+    /// Available versions of tool `name`, for `:::list --remote`
+    fn list_tool_versions(&self, _name: &str) -> XResult<Vec<String>> {
+        simple_error!("List tool versions is not supported by registry: {}", self.description())
+    }
+}
+
+pub struct HttpRegistry {
+    pub base_url: String,
+    pub auth_token: Option<String>,
+}
+
+impl Registry for HttpRegistry {
+    fn description(&self) -> String {
+        self.base_url.clone()
+    }
+
+    fn get_tool_package_detail(&self, name: &str, version: &str) -> XResult<ToolPackageDetail> {
+        let version = iff!(is_jdk_tool_name(name), get_http_registry_version(version)?, version.to_string());
+        let mut url = String::with_capacity(1024);
+        url.push_str(self.base_url.trim_end_matches('/'));
+        match &self.auth_token {
+            Some(auth_token) if !*NOAUTH => {
+                url.push_str(TOOL_PACKAGE_DETAIL_PATH);
+                url.push_str("?__auth_token=");
+                url.push_str(&urlencoding::encode(auth_token));
+            },
+            _ => {
+                url.push_str(TOOL_PACKAGE_DETAIL_PATH_WITHOUT_AUTH);
+                url.push('?');
+            },
+        }
+        url.push_str("&name=");
+        url.push_str(&urlencoding::encode(name));
+        url.push_str("&ver=");
+        url.push_str(&urlencoding::encode(&version));
+        let tool_package_detail = http::get_url_content(url.as_str())?;
+        if *VERBOSE {
+            debugging!("Get tool {}:{} from {}, result: {}", name, version, self.base_url, tool_package_detail);
+        }
+        ToolPackageDetail::parse(&tool_package_detail)
+    }
+
+    fn get_archive_version(&self, gid: &str, aid: &str) -> XResult<String> {
+        let mut url = String::with_capacity(1024);
+        url.push_str(self.base_url.trim_end_matches('/'));
+        url.push_str(GET_ARCHIVER_VERSION_PATH);
+        url.push_str("?gid=");
+        url.push_str(&urlencoding::encode(gid));
+        url.push_str("&aid=");
+        url.push_str(&urlencoding::encode(aid));
+        let version_result = http::get_url_content(url.as_str())?;
+        if *VERBOSE {
+            debugging!("Get archive result: {}", version_result);
+        }
+        let version_result_object = json::parse(&version_result)?;
+        if version_result_object["status"] != 200 {
+            simple_error!("Get archive info version failed: {}", version_result)
+        } else {
+            Ok(version_result_object["data"].to_string())
+        }
+    }
+}
+
+pub struct FileRegistry {
+    pub path: String,
+}
+
+impl FileRegistry {
+    fn get_index_file_and_dir(&self) -> (String, String) {
+        if self.path.ends_with(".json") {
+            let dir = Path::new(&self.path).parent().and_then(|p| p.to_str()).unwrap_or(".");
+            (self.path.clone(), dir.to_string())
+        } else {
+            let dir = self.path.trim_end_matches('/');
+            (format!("{}/{}", dir, FILE_REGISTRY_INDEX_JSON), dir.to_string())
+        }
+    }
+}
+
+impl Registry for FileRegistry {
+    fn description(&self) -> String {
+        format!("{}{}", FILE_URL_PREFIX, self.path)
+    }
+
+    fn get_tool_package_detail(&self, name: &str, version: &str) -> XResult<ToolPackageDetail> {
+        let (index_file, dir) = self.get_index_file_and_dir();
+        if *VERBOSE {
+            debugging!("Read file registry index: {}", index_file);
+        }
+        let index_object = json::parse(&fs::read_to_string(&index_file)?)?;
+        // exact version first, then the max version matched, e.g. `1.8` and `>=1.8` match `1.8.0_242`
+        let tools: Vec<&JsonValue> = index_object["tools"].members().filter(|tool| tool["n"].as_str() == Some(name)).collect();
+        let matched_tool = tools.iter().find(|tool| tool["v"].as_str() == Some(version)).copied().or_else(|| {
+            if !is_jdk_tool_name(name) {
+                return None;
+            }
+            let version_req = JavaVersionReq::parse(version)?;
+            tools.iter()
+                .filter_map(|tool| tool["v"].as_str().and_then(JavaVersion::parse).map(|v| (v, *tool)))
+                .filter(|(v, _)| version_req.matches(v))
+                .max_by(|(v1, _), (v2, _)| v1.cmp(v2))
+                .map(|(_, tool)| tool)
+        });
+        let tool = match matched_tool {
+            Some(tool) => tool, None => return simple_error!("Tool {}:{} not found in: {}", name, version, index_file),
+        };
+        let (file_name, integrity) = match (tool["name"].as_str(), tool["integrity"].as_str()) {
+            (Some(file_name), Some(integrity)) => (file_name, integrity),
+            _ => return simple_error!("Parse tool package detail failed: {}", tool),
+        };
+        let url = resolve_file_registry_url(&dir, tool["url"].as_str().unwrap_or(file_name));
+        let signature_url = tool["signature_url"].as_str().map(|u| resolve_file_registry_url(&dir, u));
+        Ok(ToolPackageDetail {
+            url,
+            integrity: integrity.to_string(),
+            name: file_name.to_string(),
+            n: name.to_string(),
+            v: tool["v"].to_string(),
+            size: tool["size"].as_u64(),
+            signature_url,
+        })
+    }
+    fn list_tool_versions(&self, name: &str) -> XResult<Vec<String>> {
+        let (index_file, _) = self.get_index_file_and_dir();
+        let index_object = json::parse(&fs::read_to_string(&index_file)?)?;
+        Ok(index_object["tools"].members()
+            .filter(|tool| tool["n"].as_str() == Some(name))
+            .filter_map(|tool| tool["v"].as_str().map(|v| v.to_string()))
+            .collect())
+    }
+}
+
+/// Absolute URL, or relative to the index file dir
+fn resolve_file_registry_url(dir: &str, url: &str) -> String {
+    if url.contains("://") {
+        url.to_string()
+    } else {
+        format!("{}{}/{}", FILE_URL_PREFIX, dir, url)
+    }
+}
+
+/// Tools not builders are JDKs, e.g. `jdk-linux`, `temurin-osx` or `BUILDJ_JAVA_NAME`
+fn is_jdk_tool_name(name: &str) -> bool {
+    tool::BuilderName::parse(name).is_none()
+}
+
+/// HTTP registries match the version as prefix, a range of one feature version is sent as the feature version,
+/// e.g. `17.x` -> `17`, `1.8.x` -> `1.8`, other ranges are not supported, e.g. `>=11`, `~17.0.3`
+fn get_http_registry_version(version: &str) -> XResult<String> {
+    if JavaVersion::parse(version).is_some() {
+        return Ok(version.to_string());
+    }
+    match JavaVersionReq::parse(version).and_then(|version_req| version_req.get_feature_only()) {
+        Some(feature) => Ok(iff!(feature <= 8, format!("1.{}", feature), feature.to_string())),
+        None => simple_error!("Version range {} is not supported by HTTP registries, use a version, e.g. 17.0.3", version),
+    }
+}
+
+/// Registries from build.json, tried before user config registries
+pub fn set_project_registries(registries: &[RegistryConfig]) {
+    if let Ok(mut project_registries) = PROJECT_REGISTRIES.lock() {
+        *project_registries = registries.to_vec();
+    }
+}
+
+pub fn get_registry_configs() -> Vec<RegistryConfig> {
+    let mut registry_configs = match PROJECT_REGISTRIES.lock() {
+        Ok(project_registries) => project_registries.clone(),
+        Err(_) => vec![],
+    };
+    match get_user_registry_configs() {
+        Ok(user_registry_configs) => registry_configs.extend(user_registry_configs),
+        Err(err) => if *VERBOSE {
+            debugging!("Get registries from ~/{} failed: {}", tool::STANDARD_CONFIG_JSON, err);
+        },
+    }
+    if registry_configs.is_empty() {
+        registry_configs.push(RegistryConfig { url: DEFAULT_REGISTRY_URL.to_string(), auth_token: None });
+    }
+    registry_configs
+}
+
+fn get_user_registry_configs() -> XResult<Vec<RegistryConfig>> {
+    let standard_config_object = tool::read_standard_config_object()?;
+    let registries_object = &standard_config_object["build.js"]["registries"];
+    let mut registry_configs = vec![];
+    for registry_object in registries_object.members() {
+        registry_configs.push(RegistryConfig::parse(registry_object)?);
+    }
+    Ok(registry_configs)
+}
+
+pub fn get_registries() -> Vec<Box<dyn Registry>> {
+    let mut registries: Vec<Box<dyn Registry>> = vec![];
+    for registry_config in get_registry_configs() {
+        if registry_config.url == upstream::UPSTREAM {
+            registries.extend(upstream::get_upstream_registries());
+        } else if let Some(upstream_registry) = upstream::get_upstream_registry(&registry_config.url) {
+            registries.push(upstream_registry);
+        } else if let Some(path) = get_file_registry_path(&registry_config.url) {
+            registries.push(Box::new(FileRegistry { path }));
+        } else {
+            // the default registry uses the secret of `:::config set <secret>` or BUILDJ_AUTH_TOKEN
+            let auth_token = match registry_config.auth_token {
+                Some(auth_token) => Some(auth_token),
+                None if registry_config.url == DEFAULT_REGISTRY_URL => get_default_registry_secret(),
+                None => None,
+            };
+            registries.push(Box::new(HttpRegistry { base_url: registry_config.url, auth_token }));
+        }
+    }
+    registries
+}
+
+fn get_file_registry_path(url: &str) -> Option<String> {
+    if let Some(path) = url.strip_prefix(FILE_URL_PREFIX) {
+        Some(path.to_string())
+    } else if url.starts_with('/') {
+        Some(url.to_string())
+    } else {
+        None
+    }
+}
+
+fn get_default_registry_secret() -> Option<String> {
+    if *NOAUTH {
+        warning!("Running in no auth mode!");
+        return None;
+    }
+    match tool::get_tool_package_secret() {
+        Ok(r) => Some(r), Err(err) => {
+            warning!("Get package detail secret failed: {}, from file: ~/{}", err, tool::STANDARD_CONFIG_JSON);
+            None
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_file_registry(name: &str) -> (FileRegistry, String) {
+        let test_dir = std::env::temp_dir().join(format!("buildj-test-registry-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&test_dir);
+        fs::create_dir_all(&test_dir).unwrap();
+        let mut tools: Vec<String> = ["1.8.0_392", "17.0.2", "17.0.10", "17.0.9", "21.0.1"].iter()
+            .map(|v| format!(r#"{{"n": "temurin-linux", "v": "{}", "name": "jdk-{}.tar.gz", "integrity": "sha256:hex-00"}}"#, v, v))
+            .collect();
+        tools.extend(["1.9.7", "1.10.0"].iter()
+            .map(|v| format!(r#"{{"n": "sbt", "v": "{}", "name": "sbt-{}.tgz", "integrity": "sha256:hex-00"}}"#, v, v)));
+        fs::write(test_dir.join(FILE_REGISTRY_INDEX_JSON), format!(r#"{{"tools": [{}]}}"#, tools.join(","))).unwrap();
+        let test_dir = test_dir.to_str().unwrap().to_string();
+        (FileRegistry { path: test_dir.clone() }, test_dir)
+    }
+
+    #[test]
+    fn test_file_registry_version_match() {
+        let (file_registry, test_dir) = new_file_registry("version-match");
+        let get_version = |version: &str| file_registry.get_tool_package_detail("temurin-linux", version).map(|detail| detail.v).ok();
+        assert_eq!(get_version("17.0.9"), Some("17.0.9".to_string()));
+        assert_eq!(get_version("17"), Some("17.0.10".to_string()));
+        assert_eq!(get_version("1.8"), Some("1.8.0_392".to_string()));
+        assert_eq!(get_version(">=11"), Some("21.0.1".to_string()));
+        assert_eq!(get_version(">=11 <21"), Some("17.0.10".to_string()));
+        assert_eq!(get_version("~17.0.2"), Some("17.0.10".to_string()));
+        assert_eq!(get_version("11"), None);
+        let detail = file_registry.get_tool_package_detail("temurin-linux", "21").unwrap();
+        assert_eq!(detail.url, format!("{}{}/jdk-21.0.1.tar.gz", FILE_URL_PREFIX, test_dir));
+        assert!(file_registry.get_tool_package_detail("zulu-linux", "17").is_err());
+        fs::remove_dir_all(&test_dir).unwrap();
+    }
+
+    #[test]
+    fn test_file_registry_builder_version_exact() {
+        let (file_registry, test_dir) = new_file_registry("builder-version");
+        let get_version = |version: &str| file_registry.get_tool_package_detail("sbt", version).map(|detail| detail.v).ok();
+        assert_eq!(get_version("1.9.7"), Some("1.9.7".to_string()));
+        // as Java versions `1.9` would match `1.9.7`, and `9.x` would match `1.9.7`
+        assert_eq!(get_version("1.9"), None);
+        assert_eq!(get_version("9.x"), None);
+        assert_eq!(get_version(">=1.9"), None);
+        fs::remove_dir_all(&test_dir).unwrap();
+    }
+
+    #[test]
+    fn test_file_registry_install() {
+        use std::io::Write;
+        use zip::write::{FileOptions, ZipWriter};
+        use crate::{local_util, manifest::{self, InstallState}};
+
+        let test_dir = std::env::temp_dir().join(format!("buildj-test-registry-install-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&test_dir);
+        let (registry_dir, base_dir) = (test_dir.join("registry"), test_dir.join("builder"));
+        fs::create_dir_all(&registry_dir).unwrap();
+        fs::create_dir_all(&base_dir).unwrap();
+        let mut zip_writer = ZipWriter::new(fs::File::create(registry_dir.join("apache-maven-1.0-bin.zip")).unwrap());
+        zip_writer.start_file("apache-maven-1.0/bin/mvn", FileOptions::default()).unwrap();
+        zip_writer.write_all(b"#!/bin/sh").unwrap();
+        zip_writer.finish().unwrap();
+        let package_sha256 = local_util::calc_sha256(&fs::read(registry_dir.join("apache-maven-1.0-bin.zip")).unwrap());
+        fs::write(registry_dir.join(FILE_REGISTRY_INDEX_JSON), format!(r#"{{"tools": [
+            {{"n": "maven", "v": "1.0", "name": "apache-maven-1.0-bin.zip", "integrity": "sha256:hex-{}"}},
+            {{"n": "maven", "v": "2.0", "name": "apache-maven-1.0-bin.zip", "integrity": "sha256:hex-{}"}}
+        ]}}"#, package_sha256, "0".repeat(64))).unwrap();
+
+        let file_registry = FileRegistry { path: registry_dir.to_str().unwrap().to_string() };
+        let base_dir = base_dir.to_str().unwrap();
+        let tool_package_detail = file_registry.get_tool_package_detail("maven", "1.0").unwrap();
+        tool::install_tool_package(base_dir, true, &tool_package_detail).unwrap();
+        assert_eq!(fs::read_to_string(format!("{}/maven-1.0/apache-maven-1.0/bin/mvn", base_dir)).unwrap(), "#!/bin/sh");
+        match manifest::get_install_state(&format!("{}/maven-1.0", base_dir)) {
+            InstallState::Installed(install_manifest) => assert_eq!(install_manifest.integrity, tool_package_detail.integrity),
+            _ => panic!("install manifest is not written"),
+        }
+        // installed package is not downloaded again
+        tool::install_tool_package(base_dir, true, &tool_package_detail).unwrap();
+
+        let mismatched_package_detail = file_registry.get_tool_package_detail("maven", "2.0").unwrap();
+        assert!(tool::install_tool_package(base_dir, true, &mismatched_package_detail).is_err());
+        assert!(matches!(manifest::get_install_state(&format!("{}/maven-2.0", base_dir)), InstallState::Missing));
+        fs::remove_dir_all(&test_dir).unwrap();
+    }
+
+    #[test]
+    fn test_get_http_registry_version() {
+        assert_eq!(get_http_registry_version("17.0.2").unwrap(), "17.0.2");
+        assert_eq!(get_http_registry_version("1.8").unwrap(), "1.8");
+        assert_eq!(get_http_registry_version("17.x").unwrap(), "17");
+        assert_eq!(get_http_registry_version("8.x").unwrap(), "1.8");
+        assert!(get_http_registry_version(">=11").is_err());
+        assert!(get_http_registry_version("~17.0.3").is_err());
+        assert!(is_jdk_tool_name("temurin-linux"));
+        assert!(!is_jdk_tool_name("ant"));
+    }
+}
@@ -0,0 +1,345 @@
+//! Detached minisign signature verification of downloaded packages, public keys are pinned in
+//! `~/.standard_config.json`, so a compromised registry cannot serve both package and digest:
+//! ```text
+//! {
+//!     "build.js": {
+//!         "signature": {
+//!             "public_keys": ["RWQf6LRCGA9i53mlYecO4IzT51TGPpvWucNSCh1CBM0QTaLn73Y7GFO3"],
+//!             "strict": true
+//!         }
+//!     }
+//! }
+//! ```
+//! Signature URL is `signature_url` from registry, defaults to `<package url>.minisig`.
+//! Without strict, a package without signature (HTTP 404) is accepted with a warning, other download errors and
+//! an invalid signature always fail.
+use std::fs::{self, File};
+use std::io::{Read, ErrorKind};
+use crypto::{blake2b::Blake2b, digest::Digest, ed25519};
+use rust_util::XResult;
+use rust_util::util_io::DEFAULT_BUF_SIZE;
+
+use crate::{http, tool, registry::ToolPackageDetail, misc::VERBOSE};
+
+const SIGNATURE_FILE_SUFFIX: &str = ".minisig";
+const UNTRUSTED_COMMENT_PREFIX: &str = "untrusted comment:";
+const TRUSTED_COMMENT_PREFIX: &str = "trusted comment: ";
+/// Signs the file content
+const SIGNATURE_ALG_LEGACY: &[u8] = b"Ed";
+/// Signs BLAKE2b-512 of the file content
+const SIGNATURE_ALG_PREHASHED: &[u8] = b"ED";
+const KEY_ID_LEN: usize = 8;
+
+pub struct SignatureConfig {
+    pub public_keys: Vec<PublicKey>,
+    pub strict: bool,
+}
+
+pub struct PublicKey {
+    pub key_id: [u8; KEY_ID_LEN],
+    pub key: [u8; 32],
+}
+
+struct Signature {
+    alg: Vec<u8>,
+    key_id: [u8; KEY_ID_LEN],
+    signature: [u8; 64],
+    trusted_comment: String,
+    global_signature: [u8; 64],
+}
+
+impl SignatureConfig {
+    pub fn is_enabled(&self) -> bool {
+        self.strict || !self.public_keys.is_empty()
+    }
+}
+
+impl PublicKey {
+    /// Base64 public key, or minisign `.pub` file content with untrusted comment
+    pub fn parse(public_key: &str) -> XResult<PublicKey> {
+        let public_key_base64 = match public_key.lines().map(|l| l.trim()).filter(|l| !l.is_empty())
+            .find(|l| !l.starts_with(UNTRUSTED_COMMENT_PREFIX)) {
+            Some(public_key_base64) => public_key_base64,
+            None => return simple_error!("Public key is empty"),
+        };
+        let public_key_bytes = base64::decode(public_key_base64)?;
+        if public_key_bytes.len() != 2 + KEY_ID_LEN + 32 || &public_key_bytes[0..2] != SIGNATURE_ALG_LEGACY {
+            return simple_error!("Not supported public key: {}", public_key_base64);
+        }
+        let mut key_id = [0_u8; KEY_ID_LEN];
+        key_id.copy_from_slice(&public_key_bytes[2..2 + KEY_ID_LEN]);
+        let mut key = [0_u8; 32];
+        key.copy_from_slice(&public_key_bytes[2 + KEY_ID_LEN..]);
+        Ok(PublicKey { key_id, key })
+    }
+}
+
+impl Signature {
+    fn parse(signature: &str) -> XResult<Signature> {
+        let lines = signature.lines().map(|l| l.trim_end()).filter(|l| !l.is_empty()).collect::<Vec<_>>();
+        let (signature_base64, trusted_comment, global_signature_base64) = match lines.as_slice() {
+            [untrusted_comment, signature_base64, trusted_comment, global_signature_base64]
+            if untrusted_comment.starts_with(UNTRUSTED_COMMENT_PREFIX) => {
+                match trusted_comment.strip_prefix(TRUSTED_COMMENT_PREFIX) {
+                    Some(trusted_comment) => (signature_base64, trusted_comment, global_signature_base64),
+                    None => return simple_error!("Signature trusted comment not found"),
+                }
+            },
+            _ => return simple_error!("Signature format error, expected 4 lines"),
+        };
+        let signature_bytes = base64::decode(signature_base64)?;
+        let global_signature_bytes = base64::decode(global_signature_base64)?;
+        if signature_bytes.len() != 2 + KEY_ID_LEN + 64 || global_signature_bytes.len() != 64 {
+            return simple_error!("Signature length error");
+        }
+        let mut key_id = [0_u8; KEY_ID_LEN];
+        key_id.copy_from_slice(&signature_bytes[2..2 + KEY_ID_LEN]);
+        let mut signature = [0_u8; 64];
+        signature.copy_from_slice(&signature_bytes[2 + KEY_ID_LEN..]);
+        let mut global_signature = [0_u8; 64];
+        global_signature.copy_from_slice(&global_signature_bytes);
+        Ok(Signature {
+            alg: signature_bytes[0..2].to_vec(),
+            key_id,
+            signature,
+            trusted_comment: trusted_comment.to_string(),
+            global_signature,
+        })
+    }
+}
+
+/// Read `build.js.signature` in `~/.standard_config.json`, missing config means signature is disabled
+pub fn get_signature_config() -> XResult<SignatureConfig> {
+    let standard_config_object = match tool::read_standard_config_object() {
+        Ok(standard_config_object) => standard_config_object,
+        Err(_) => return Ok(SignatureConfig { public_keys: vec![], strict: false }),
+    };
+    let signature_object = &standard_config_object["build.js"]["signature"];
+    let mut public_keys = vec![];
+    if let Some(public_key) = signature_object["public_keys"].as_str() {
+        public_keys.push(PublicKey::parse(public_key)?);
+    }
+    for public_key in signature_object["public_keys"].members() {
+        match public_key.as_str() {
+            Some(public_key) => public_keys.push(PublicKey::parse(public_key)?),
+            None => return simple_error!("Standard json#build.js#signature#public_keys must be strings: {}", public_key),
+        }
+    }
+    Ok(SignatureConfig {
+        public_keys,
+        strict: signature_object["strict"].as_bool().unwrap_or(false),
+    })
+}
+
+/// Download signature to `<file_name>.minisig` and verify, fails when signature is missing in strict mode
+pub fn verify_package_signature(tool_package_detail: &ToolPackageDetail, file_name: &str) -> XResult<()> {
+    verify_package_signature_with_config(&get_signature_config()?, tool_package_detail, file_name)
+}
+
+/// Only a missing signature (HTTP 404) is skipped without strict, other download errors always fail
+fn verify_package_signature_with_config(signature_config: &SignatureConfig, tool_package_detail: &ToolPackageDetail,
+                                        file_name: &str) -> XResult<()> {
+    if !signature_config.is_enabled() {
+        return Ok(());
+    }
+    if signature_config.public_keys.is_empty() {
+        return simple_error!("Signature strict mode requires public keys in standard json#build.js#signature#public_keys");
+    }
+    let signature_url = match &tool_package_detail.signature_url {
+        Some(signature_url) => signature_url.clone(),
+        None => format!("{}{}", tool_package_detail.url, SIGNATURE_FILE_SUFFIX),
+    };
+    let signature_file_name = format!("{}{}", file_name, SIGNATURE_FILE_SUFFIX);
+    information!("Start download signature: {}", signature_url);
+    let signature_download_result = http::download_url(&signature_url, &signature_file_name, None, None)
+        .and_then(|_| Ok(fs::read_to_string(&signature_file_name)?));
+    let _ = fs::remove_file(&signature_file_name);
+    let signature = match signature_download_result {
+        Ok(signature) => signature,
+        Err(err) if signature_config.strict => {
+            return simple_error!("Signature is required in strict mode, get signature: {} failed: {}", signature_url, err);
+        },
+        Err(err) if err.is::<http::NotFoundError>() => {
+            warning!("Get signature: {} failed: {}, skip verify signature, set build.js#signature#strict to require signature",
+                     signature_url, err);
+            return Ok(());
+        },
+        Err(err) => return simple_error!("Get signature: {} failed: {}", signature_url, err),
+    };
+    if !verify_file_signature(signature_config, file_name, &signature)? {
+        return simple_error!("Verify signature failed: {}", signature_url);
+    }
+    success!("Verify signature success.");
+    Ok(())
+}
+
+/// Verify `file_name` with signature content, `Ok(false)` when signature is invalid or signed by an unknown key
+pub fn verify_file_signature(signature_config: &SignatureConfig, file_name: &str, signature: &str) -> XResult<bool> {
+    let signature = Signature::parse(signature)?;
+    let public_key = match signature_config.public_keys.iter().find(|k| k.key_id == signature.key_id) {
+        Some(public_key) => public_key,
+        None => {
+            failure!("Signature key id: {} is not in configured public keys", hex_key_id(&signature.key_id));
+            return Ok(false);
+        },
+    };
+    let message = if signature.alg == SIGNATURE_ALG_PREHASHED {
+        calc_file_blake2b(file_name)?
+    } else if signature.alg == SIGNATURE_ALG_LEGACY {
+        fs::read(file_name)?
+    } else {
+        return simple_error!("Not supported signature algorithm: {}", String::from_utf8_lossy(&signature.alg));
+    };
+    if !ed25519::verify(&message, &public_key.key, &signature.signature) {
+        failure!("Verify signature failed, key id: {}", hex_key_id(&signature.key_id));
+        return Ok(false);
+    }
+    let mut global_message = signature.signature.to_vec();
+    global_message.extend_from_slice(signature.trusted_comment.as_bytes());
+    if !ed25519::verify(&global_message, &public_key.key, &signature.global_signature) {
+        failure!("Verify signature trusted comment failed, key id: {}", hex_key_id(&signature.key_id));
+        return Ok(false);
+    }
+    if *VERBOSE {
+        debugging!("Signature trusted comment: {}", signature.trusted_comment);
+    }
+    Ok(true)
+}
+
+fn calc_file_blake2b(file_name: &str) -> XResult<Vec<u8>> {
+    let mut blake2b = Blake2b::new(64);
+    let mut buf = [0_u8; DEFAULT_BUF_SIZE];
+    let mut f = File::open(file_name)?;
+    loop {
+        let len = match f.read(&mut buf) {
+            Ok(0) => break,
+            Ok(len) => len,
+            Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(Box::new(e)),
+        };
+        blake2b.input(&buf[..len]);
+    }
+    let mut digest = vec![0_u8; 64];
+    blake2b.result(&mut digest);
+    Ok(digest)
+}
+
+fn hex_key_id(key_id: &[u8]) -> String {
+    // minisign prints key id in little endian
+    key_id.iter().rev().map(|b| format!("{:02X}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Generated with an independent Ed25519 implementation, secret key seed is bytes 0..32, key id is 01..08
+    const PUBLIC_KEY: &str = "untrusted comment: minisign public key 0807060504030201\n\
+        RWQBAgMEBQYHCAOhB7/zzhC+HXDdGOdLwJln5NYwm6UNXx3chmQSVTG4\n";
+    /// Same key with key id 08..01
+    const OTHER_KEY_ID_PUBLIC_KEY: &str = "RWQIBwYFBAMCAQOhB7/zzhC+HXDdGOdLwJln5NYwm6UNXx3chmQSVTG4";
+    const PAYLOAD: &[u8] = b"buildj test package\n";
+    const SIGNATURE_LEGACY: &str = "untrusted comment: signature from minisign secret key\n\
+        RWQBAgMEBQYHCHEG6PcuqcIgOLGdPbpODnZq8lFyjTQHhqT5DhzY1A+uk6T+d3KadQsXmKKxjEjs6UcqIGllPCFRuIVypCWnSwg=\n\
+        trusted comment: timestamp:1700000000\tfile:package.tar.gz\n\
+        MbHqI0KtJm1hW3uEdQbhbjkz3jdcjUEvocXEntHuGwbtvbNtoT5HIlRvjfaxKwS1h/tCFCy0OHmM9040vPl4Ag==\n";
+    const SIGNATURE_PREHASHED: &str = "untrusted comment: signature from minisign secret key\n\
+        RUQBAgMEBQYHCOEfjYQHRnbUVdR7fetEQOFVc/y9ii5zOn2F2ofJGyz7LjHbs29+PjlAf9cWWN3rx2LYDNunOlcSZoYluVrQxAc=\n\
+        trusted comment: timestamp:1700000000\tfile:package.tar.gz\n\
+        7aWIBYTy0mW9bQO0udSCw3XgsrSXDNHsOsYU5V7fZS0DTboZ/7qP5Knazb5sT1+eu0P7HiuTIBzyu4apZpwRAg==\n";
+
+    fn new_test_file(name: &str, content: &[u8]) -> String {
+        let test_file = std::env::temp_dir().join(format!("buildj-test-signature-{}-{}", name, std::process::id()));
+        fs::write(&test_file, content).unwrap();
+        test_file.to_str().unwrap().to_string()
+    }
+
+    fn new_signature_config(public_key: &str, strict: bool) -> SignatureConfig {
+        SignatureConfig { public_keys: vec![PublicKey::parse(public_key).unwrap()], strict }
+    }
+
+    fn verify(name: &str, payload: &[u8], public_key: &str, signature: &str) -> bool {
+        let file_name = new_test_file(name, payload);
+        let verified = verify_file_signature(&new_signature_config(public_key, false), &file_name, signature).unwrap();
+        fs::remove_file(&file_name).unwrap();
+        verified
+    }
+
+    #[test]
+    fn test_parse_public_key() {
+        let public_key = PublicKey::parse(PUBLIC_KEY).unwrap();
+        assert_eq!(public_key.key_id, [1, 2, 3, 4, 5, 6, 7, 8]);
+        assert_eq!(hex_key_id(&public_key.key_id), "0807060504030201");
+        assert!(PublicKey::parse("RWQBAgMEBQYHCA==").is_err());
+    }
+
+    #[test]
+    fn test_verify_valid_signature() {
+        assert!(verify("valid", PAYLOAD, PUBLIC_KEY, SIGNATURE_LEGACY));
+    }
+
+    #[test]
+    fn test_verify_prehashed_signature() {
+        assert!(verify("prehashed", PAYLOAD, PUBLIC_KEY, SIGNATURE_PREHASHED));
+        assert!(!verify("prehashed-tampered", b"buildj test package!", PUBLIC_KEY, SIGNATURE_PREHASHED));
+    }
+
+    #[test]
+    fn test_verify_tampered_payload() {
+        assert!(!verify("tampered", b"buildj test package!", PUBLIC_KEY, SIGNATURE_LEGACY));
+    }
+
+    #[test]
+    fn test_verify_wrong_key_id() {
+        assert!(!verify("wrong-key-id", PAYLOAD, OTHER_KEY_ID_PUBLIC_KEY, SIGNATURE_LEGACY));
+    }
+
+    #[test]
+    fn test_verify_modified_trusted_comment() {
+        let signature = SIGNATURE_LEGACY.replace("timestamp:1700000000", "timestamp:1700000001");
+        assert!(!verify("trusted-comment", PAYLOAD, PUBLIC_KEY, &signature));
+    }
+
+    #[test]
+    fn test_verify_malformed_signature() {
+        let file_name = new_test_file("malformed", PAYLOAD);
+        let signature_config = new_signature_config(PUBLIC_KEY, false);
+        let signature = SIGNATURE_LEGACY.replace("trusted comment: ", "comment: ");
+        assert!(verify_file_signature(&signature_config, &file_name, &signature).is_err());
+        assert!(verify_file_signature(&signature_config, &file_name, "untrusted comment: x\n").is_err());
+        fs::remove_file(&file_name).unwrap();
+    }
+
+    #[test]
+    fn test_verify_package_signature_missing() {
+        let file_name = new_test_file("missing", PAYLOAD);
+        let tool_package_detail = ToolPackageDetail {
+            url: format!("file://{}", file_name),
+            integrity: String::new(),
+            name: "package.tar.gz".to_string(),
+            n: "test".to_string(),
+            v: "1.0".to_string(),
+            size: None,
+            signature_url: None,
+        };
+        let strict_config = new_signature_config(PUBLIC_KEY, true);
+        assert!(verify_package_signature_with_config(&strict_config, &tool_package_detail, &file_name).is_err());
+        let non_strict_config = new_signature_config(PUBLIC_KEY, false);
+        assert!(verify_package_signature_with_config(&non_strict_config, &tool_package_detail, &file_name).is_ok());
+        // reading a dir fails, only a missing signature is skipped
+        let broken_tool_package_detail = ToolPackageDetail {
+            signature_url: Some(format!("file://{}", std::env::temp_dir().display())),
+            ..tool_package_detail.clone()
+        };
+        assert!(verify_package_signature_with_config(&non_strict_config, &broken_tool_package_detail, &file_name).is_err());
+
+        let signature_file_name = new_test_file("missing.minisig", SIGNATURE_LEGACY.as_bytes());
+        let signed_tool_package_detail = ToolPackageDetail {
+            signature_url: Some(format!("file://{}", signature_file_name)),
+            ..tool_package_detail
+        };
+        assert!(verify_package_signature_with_config(&strict_config, &signed_tool_package_detail, &file_name).is_ok());
+        fs::remove_file(&signature_file_name).unwrap();
+        fs::remove_file(&file_name).unwrap();
+    }
+}
@@ -1,20 +1,84 @@
-use std::{fs::{self, File}, path::Path};
-use rust_util::{ XResult, util_os};
-use crate::{http, local_util, misc::{AUTH_TOKEN, VERBOSE, NOAUTH}};
+use std::{env, fs, path::Path, collections::HashSet};
+use rust_util::{XResult, util_env, util_os};
+use crate::{http, local_util, registry::{self, ToolPackageDetail}, misc::{AUTH_TOKEN, VERBOSE}};
+use crate::manifest::{self, InstallManifest, InstallState, STAGING_DIR_PREFIX};
+use crate::lock::InstallLock;
+use crate::{archive, signature, lockfile::{self, LockedTool}};
 
 const M2_HOME: &str = "M2_HOME";
 const MAVEN_HOME: &str = "MAVEN_HOME";
 const GRADLE_HOME: &str = "GRADLE_HOME";
+const ANT_HOME: &str = "ANT_HOME";
+const SBT_HOME: &str = "SBT_HOME";
 
 pub const LOCAL_BUILDER_HOME_BASE_DIR: &str = ".jssp/builder";
 pub const STANDARD_CONFIG_JSON: &str = ".standard_config.json";
-const TOOL_PACKAGE_DETAIL_URL: &str = "https://hatter.ink/tool/query_tool_by_name_version.json";
-const TOOL_PACKAGE_DETAIL_URL_WITHOUT_AUTH: &str = "https://hatter.ink/tool/query_tool_by_name_version_without_auth.json";
+const DOWNLOAD_FILE_PREFIX: &str = ".download-";
+
+const SDKMAN_CANDIDATES_DIR: &str = ".sdkman/candidates";
+const MAVEN_WRAPPER_DISTS_DIR: &str = ".m2/wrapper/dists";
+const GRADLE_WRAPPER_DISTS_DIR: &str = ".gradle/wrapper/dists";
+
+lazy_static! {
+    pub static ref NO_SYSTEM_BUILDER: bool = util_env::is_env_on("BUILDJ_NO_SYSTEM_BUILDER");
+}
 
 #[derive(Clone, Copy)]
 pub enum BuilderName {
     Maven,
     Gradle,
+    Ant,
+    Sbt,
+}
+
+const ALL_BUILDER_NAMES: [BuilderName; 4] = [
+    BuilderName::Maven,
+    BuilderName::Gradle,
+    BuilderName::Ant,
+    BuilderName::Sbt,
+];
+
+impl BuilderName {
+    pub fn parse(builder: &str) -> Option<BuilderName> {
+        ALL_BUILDER_NAMES.iter().find(|b| b.name() == builder).copied()
+    }
+
+    pub fn all() -> &'static [BuilderName] {
+        &ALL_BUILDER_NAMES
+    }
+
+    /// Name in build.json, built-in commands and tool registry
+    pub fn name(self) -> &'static str {
+        match self {
+            BuilderName::Maven => "maven",
+            BuilderName::Gradle => "gradle",
+            BuilderName::Ant => "ant",
+            BuilderName::Sbt => "sbt",
+        }
+    }
+
+    /// Executable in `bin`
+    pub fn bin_name(self) -> &'static str {
+        match self {
+            BuilderName::Maven => "mvn",
+            BuilderName::Gradle => "gradle",
+            BuilderName::Ant => "ant",
+            BuilderName::Sbt => "sbt",
+        }
+    }
+
+    /// Prefixes of the jars in `lib` with the version in name, e.g. `lib/maven-core-3.5.2.jar`
+    pub fn version_jar_prefixes(self) -> &'static [&'static str] {
+        match self {
+            BuilderName::Maven => &["maven-core-"],
+            BuilderName::Gradle => &["gradle-launcher-", "gradle-core-"],
+            BuilderName::Ant | BuilderName::Sbt => &[],
+        }
+    }
+
+    pub fn supported_names() -> String {
+        ALL_BUILDER_NAMES.iter().map(|b| b.name()).collect::<Vec<_>>().join(", ")
+    }
 }
 
 pub struct BuilderDesc {
@@ -28,41 +92,264 @@ impl BuilderDesc {
         match self.name {
             BuilderName::Maven => vec![M2_HOME.to_string(), MAVEN_HOME.to_string()],
             BuilderName::Gradle => vec![GRADLE_HOME.to_string()],
+            BuilderName::Ant => vec![ANT_HOME.to_string()],
+            BuilderName::Sbt => vec![SBT_HOME.to_string()],
         }
     }
 
     pub fn get_builder_bin(&self) -> String {
         match &self.bin {
             Some(b) => b.clone(),
-            None => match self.name {
-                BuilderName::Maven => format!("{}/bin/mvn", self.home.clone()),
-                BuilderName::Gradle => format!("{}/bin/gradle", self.home.clone()),
-            }
+            None => format!("{}/bin/{}", self.home, self.name.bin_name()),
         }
     }
 }
 
 pub fn get_builder_home(builder: &str, version: &str) -> Option<BuilderDesc> {
-    let local_builder_home_base_dir = match local_util::get_user_home_dir(LOCAL_BUILDER_HOME_BASE_DIR) {
-        Ok(o) => o, Err(_) => return None,
-    };
-    let builder_name = match builder {
-        "maven" => BuilderName::Maven,
-        "gradle" => BuilderName::Gradle,
-        _ => {
+    let builder_name = match BuilderName::parse(builder) {
+        Some(builder_name) => builder_name,
+        None => {
             failure!("Unknown builder: {}", builder);
             return None;
         },
     };
-    let local_builder_home_dir = &format!("{}/{}-{}", local_builder_home_base_dir, builder, version);
+    let local_builder_home_dir = &get_local_builder_home_dir(builder, version)?;
 
-    if Path::new(local_builder_home_dir).exists() || get_cloud_builder(builder, version) {
+    let install_state = get_builder_install_state(builder_name, local_builder_home_dir);
+    match &install_state {
+        InstallState::Installed(_) => return get_local_builder_home_sub(builder_name, local_builder_home_dir),
+        InstallState::Legacy if adopt_legacy_builder(builder_name, version, local_builder_home_dir) => {
+            return get_local_builder_home_sub(builder_name, local_builder_home_dir);
+        },
+        _ => (),
+    }
+    if let Some(builder_desc) = get_system_builder_home(builder_name, version) {
+        return Some(builder_desc);
+    }
+    if let InstallState::Broken(reason) = &install_state {
+        warning!("Builder {} is broken: {}, re-install", local_builder_home_dir, reason);
+    }
+    if get_cloud_builder(builder, version) {
         get_local_builder_home_sub(builder_name, local_builder_home_dir)
     } else {
         None
     }
 }
 
+/// Builder pinned in `build.lock.json`, installed from the locked URL when not found
+pub fn get_locked_builder_home(locked_builder: &LockedTool, download: bool) -> Option<BuilderDesc> {
+    let builder_name = match BuilderName::parse(&locked_builder.name) {
+        Some(builder_name) => builder_name,
+        None => {
+            failure!("Unknown builder: {}", locked_builder.name);
+            return None;
+        },
+    };
+    let local_builder_home_dir = &get_local_builder_home_dir(&locked_builder.name, &locked_builder.version)?;
+    if is_locked_builder_installed(locked_builder, local_builder_home_dir) {
+        return get_local_builder_home_sub(builder_name, local_builder_home_dir);
+    }
+    if !download {
+        return None;
+    }
+    let local_builder_home_base_dir = local_util::get_user_home_dir(LOCAL_BUILDER_HOME_BASE_DIR).ok()?;
+    information!("Install locked builder: {} {}", locked_builder.name, locked_builder.version);
+    if let Err(err) = install_tool_package(&local_builder_home_base_dir, true, &locked_builder.to_tool_package_detail()) {
+        failure!("Install locked builder: {} {} failed: {}", locked_builder.name, locked_builder.version, err);
+        return None;
+    }
+    get_local_builder_home_sub(builder_name, local_builder_home_dir)
+}
+
+fn is_locked_builder_installed(locked_builder: &LockedTool, local_builder_home_dir: &str) -> bool {
+    match manifest::get_install_state(local_builder_home_dir) {
+        InstallState::Installed(install_manifest) => {
+            if !locked_builder.matches(&install_manifest) {
+                warning!("Builder {} does not match {}, integrity: {} vs {}", local_builder_home_dir,
+                         lockfile::BUILD_LOCK_JSON, install_manifest.integrity, locked_builder.integrity);
+            }
+            locked_builder.matches(&install_manifest)
+        },
+        _ => false,
+    }
+}
+
+/// Same as `manifest::get_install_state`, but a builder dir without install manifest with
+/// `<dir>/<sub dir>/bin/<bin name>`, e.g. `maven-3.5.2/apache-maven-3.5.2/bin/mvn`, is `Legacy`
+pub fn get_builder_install_state(builder_name: BuilderName, local_builder_home_dir: &str) -> InstallState {
+    let install_state = manifest::get_install_state(local_builder_home_dir);
+    if !matches!(install_state, InstallState::Broken(_))
+        || Path::new(&format!("{}/{}", local_builder_home_dir, manifest::INSTALL_MANIFEST_JSON)).exists() {
+        return install_state;
+    }
+    let has_builder_bin = get_local_builder_home_sub_first_sub_dir(local_builder_home_dir)
+        .map(|builder_home| local_util::is_path_exists(&builder_home, &format!("bin/{}", builder_name.bin_name())))
+        .unwrap_or(false);
+    iff!(has_builder_bin, InstallState::Legacy, install_state)
+}
+
+/// Adopt a `Legacy` builder dir by writing an install manifest with source `legacy`,
+/// only when the builder home is complete, see `is_builder_home_complete`
+fn adopt_legacy_builder(builder_name: BuilderName, version: &str, local_builder_home_dir: &str) -> bool {
+    let is_complete = get_local_builder_home_sub_first_sub_dir(local_builder_home_dir)
+        .map(|builder_home| is_builder_home_complete(builder_name, version, &builder_home))
+        .unwrap_or(false);
+    if !is_complete {
+        warning!("Legacy builder {} is incomplete, re-install", local_builder_home_dir);
+        return false;
+    }
+    if let Err(err) = InstallManifest::new_legacy(builder_name.name(), version).write_to(local_builder_home_dir) {
+        warning!("Adopt legacy builder {} failed: {}", local_builder_home_dir, err);
+        return false;
+    }
+    information!("Adopt legacy builder: {}", local_builder_home_dir);
+    true
+}
+
+/// Bin and jars of the builder exist, and the version in `lib` jar names matches when the builder has one,
+/// e.g. `bin/mvn` and `lib/maven-core-3.5.2.jar`
+fn is_builder_home_complete(builder_name: BuilderName, version: &str, builder_home: &str) -> bool {
+    if !Path::new(&format!("{}/bin/{}", builder_home, builder_name.bin_name())).is_file() {
+        return false;
+    }
+    match builder_name {
+        BuilderName::Maven | BuilderName::Gradle => {
+            read_builder_version(builder_home, builder_name.version_jar_prefixes()).as_deref() == Some(version)
+        },
+        BuilderName::Ant => local_util::is_path_exists(builder_home, "lib/ant.jar")
+            && local_util::is_path_exists(builder_home, "lib/ant-launcher.jar"),
+        BuilderName::Sbt => local_util::is_path_exists(builder_home, "bin/sbt-launch.jar"),
+    }
+}
+
+/// Same as `get_builder_home`, but never downloads
+pub fn get_local_builder_home(builder: &str, version: &str) -> Option<BuilderDesc> {
+    let builder_name = BuilderName::parse(builder)?;
+    let local_builder_home_dir = &get_local_builder_home_dir(builder, version)?;
+
+    match get_builder_install_state(builder_name, local_builder_home_dir) {
+        InstallState::Installed(_) => get_local_builder_home_sub(builder_name, local_builder_home_dir),
+        InstallState::Legacy => {
+            let builder_desc = get_local_builder_home_sub(builder_name, local_builder_home_dir)?;
+            if is_builder_home_complete(builder_name, version, &builder_desc.home) {
+                Some(builder_desc)
+            } else {
+                warning!("Legacy builder {} is incomplete", local_builder_home_dir);
+                get_system_builder_home(builder_name, version)
+            }
+        },
+        InstallState::Missing => get_system_builder_home(builder_name, version),
+        InstallState::Broken(reason) => {
+            warning!("Builder {} is broken: {}", local_builder_home_dir, reason);
+            get_system_builder_home(builder_name, version)
+        },
+    }
+}
+
+/// Maven or Gradle not installed by buildj, found in `MAVEN_HOME`, `M2_HOME` or `GRADLE_HOME`, `PATH`, SDKMAN,
+/// then the Maven / Gradle wrapper dists, the version is read from `lib`, e.g. `lib/maven-core-3.5.2.jar`,
+/// disabled by `BUILDJ_NO_SYSTEM_BUILDER=1` or standard json#build.js#system_builder = false
+pub fn get_system_builder_home(builder_name: BuilderName, version: &str) -> Option<BuilderDesc> {
+    let (_, builder_home) = list_system_builders(builder_name).into_iter()
+        .find(|(builder_version, _)| builder_version == version)?;
+    success!("Find system builder: {} {} @ {}", builder_name.name(), version, builder_home);
+    Some(BuilderDesc { name: builder_name, home: builder_home, bin: None })
+}
+
+/// Versions and homes of system builders in search order, see `get_system_builder_home`
+pub fn list_system_builders(builder_name: BuilderName) -> Vec<(String, String)> {
+    if !is_system_builder_enabled() {
+        return vec![];
+    }
+    let (home_envs, wrapper_dists_dir) = match builder_name {
+        BuilderName::Maven => (vec![MAVEN_HOME, M2_HOME], MAVEN_WRAPPER_DISTS_DIR),
+        BuilderName::Gradle => (vec![GRADLE_HOME], GRADLE_WRAPPER_DISTS_DIR),
+        _ => return vec![],
+    };
+    let mut builder_homes: Vec<String> = home_envs.iter().filter_map(|home_env| env::var(home_env).ok()).collect();
+    // `<home>/bin/mvn`
+    if let Some(builder_bin) = local_util::find_in_path(builder_name.bin_name()) {
+        if let Some(builder_home) = Path::new(&builder_bin).parent().and_then(|bin_dir| bin_dir.parent()) {
+            builder_homes.extend(builder_home.to_str().map(|h| h.to_string()));
+        }
+    }
+    if let Ok(user_home) = local_util::get_user_home() {
+        builder_homes.extend(local_util::list_sub_dirs(&format!("{}/{}/{}", user_home, SDKMAN_CANDIDATES_DIR, builder_name.name())));
+        // e.g. `~/.gradle/wrapper/dists/gradle-7.6-bin/<hash>/gradle-7.6`
+        for dist_dir in local_util::list_sub_dirs(&format!("{}/{}", user_home, wrapper_dists_dir)) {
+            let dist_name = Path::new(&dist_dir).file_name().and_then(|n| n.to_str()).unwrap_or("").to_string();
+            for dist_hash_dir in local_util::list_sub_dirs(&dist_dir) {
+                // gradle writes `<hash>/gradle-7.6-bin.zip.ok` after the dist is completely unzipped
+                if matches!(builder_name, BuilderName::Gradle) && !local_util::is_path_exists(&dist_hash_dir, &format!("{}.zip.ok", dist_name)) {
+                    continue;
+                }
+                // maven wrapper has no marker, check the files mvn needs to start
+                builder_homes.extend(local_util::list_sub_dirs(&dist_hash_dir).into_iter()
+                    .filter(|builder_home| !matches!(builder_name, BuilderName::Maven) || is_maven_dist_complete(builder_home)));
+            }
+        }
+    }
+    let mut canonical_builder_homes = HashSet::new();
+    let mut system_builders = vec![];
+    for builder_home in builder_homes {
+        if !local_util::is_path_exists(&builder_home, &format!("bin/{}", builder_name.bin_name())) {
+            continue;
+        }
+        // e.g. SDKMAN `current` is a symlink
+        if !canonical_builder_homes.insert(fs::canonicalize(&builder_home).unwrap_or_else(|_| builder_home.clone().into())) {
+            continue;
+        }
+        let builder_version = read_builder_version(&builder_home, builder_name.version_jar_prefixes());
+        if *VERBOSE {
+            debugging!("Check system builder: {}, version: {:?}", builder_home, builder_version);
+        }
+        if let Some(builder_version) = builder_version {
+            system_builders.push((builder_version, builder_home));
+        }
+    }
+    system_builders
+}
+
+/// `bin/mvn`, `bin/m2.conf`, `boot` and `lib` of an unzipped maven dist
+fn is_maven_dist_complete(maven_home: &str) -> bool {
+    ["bin/mvn", "bin/m2.conf"].iter().all(|f| Path::new(maven_home).join(f).is_file())
+        && ["boot", "lib"].iter().all(|d| Path::new(maven_home).join(d).is_dir())
+}
+
+fn is_system_builder_enabled() -> bool {
+    if *NO_SYSTEM_BUILDER {
+        return false;
+    }
+    read_standard_config_object()
+        .map(|standard_config_object| standard_config_object["build.js"]["system_builder"].as_bool().unwrap_or(true))
+        .unwrap_or(true)
+}
+
+/// Version from jar name in `lib`, jar may be a symlink to a versioned jar, e.g. Debian `maven-core.jar`
+fn read_builder_version(builder_home: &str, version_jar_prefixes: &[&str]) -> Option<String> {
+    let lib_dir = fs::read_dir(Path::new(builder_home).join("lib")).ok()?;
+    for dir_entry in lib_dir.flatten() {
+        let jar_path = fs::canonicalize(dir_entry.path()).unwrap_or_else(|_| dir_entry.path());
+        let jar_name = match jar_path.file_name().and_then(|n| n.to_str()) {
+            Some(jar_name) => jar_name, None => continue,
+        };
+        for version_jar_prefix in version_jar_prefixes {
+            let jar_version = jar_name.strip_prefix(version_jar_prefix).and_then(|n| n.strip_suffix(".jar"));
+            if let Some(jar_version) = jar_version {
+                if jar_version.starts_with(|c: char| c.is_ascii_digit()) {
+                    return Some(jar_version.to_string());
+                }
+            }
+        }
+    }
+    None
+}
+
+fn get_local_builder_home_dir(builder: &str, version: &str) -> Option<String> {
+    let local_builder_home_base_dir = local_util::get_user_home_dir(LOCAL_BUILDER_HOME_BASE_DIR).ok()?;
+    Some(format!("{}/{}-{}", local_builder_home_base_dir, builder, version))
+}
+
 pub fn get_cloud_builder(builder: &str, version: &str) -> bool {
     if ! util_os::is_macos_or_linux() {
         return false;
@@ -90,11 +377,9 @@ pub fn get_local_builder_home_sub(builder_name: BuilderName, local_builder_home_
 
 pub fn get_local_builder_home_sub_first_sub_dir(local_builder_home_dir: &str) -> Option<String> {
     let paths = fs::read_dir(Path::new(&local_builder_home_dir)).ok()?;
-    for path in paths {
-        if let Ok(p) = path {
-            if p.path().is_dir() {
-                return Some(p.path().to_str()?.to_string());
-            }
+    for p in paths.flatten() {
+        if p.path().is_dir() {
+            return Some(p.path().to_str()?.to_string());
         }
     }
     None
@@ -108,9 +393,7 @@ pub fn get_tool_package_secret() -> XResult<String> {
         return Ok((*AUTH_TOKEN).as_ref().unwrap().clone());
     }
 
-    let standard_config_file = local_util::get_user_home_dir(STANDARD_CONFIG_JSON)?;
-    let standard_config_json = fs::read_to_string(&standard_config_file)?;
-    let standard_config_object = json::parse(&standard_config_json)?;
+    let standard_config_object = read_standard_config_object()?;
 
     let build_js_auth_token = &standard_config_object["build.js"]["auth_token"];
     
@@ -121,6 +404,12 @@ pub fn get_tool_package_secret() -> XResult<String> {
     }
 }
 
+pub fn read_standard_config_object() -> XResult<json::JsonValue> {
+    let standard_config_file = local_util::get_user_home_dir(STANDARD_CONFIG_JSON)?;
+    let standard_config_json = fs::read_to_string(&standard_config_file)?;
+    Ok(json::parse(&standard_config_json)?)
+}
+
 pub fn set_tool_package_secret(secret: &str) -> XResult<()> {
     let standard_config_file = local_util::get_user_home_dir(STANDARD_CONFIG_JSON)?;
 
@@ -155,83 +444,193 @@ pub fn set_tool_package_secret(secret: &str) -> XResult<()> {
     }
 }
 
-pub fn get_tool_package_detail(name: &str, version: &str) -> XResult<String> {
-    let secret: Option<String> = if *NOAUTH {
-        warning!("Running in no auth mode!");
-        None
-    } else {
-        match get_tool_package_secret() {
-            Ok(r) => Some(r), Err(err) => {
-                warning!("Get package detail secret failed: {}, from file: ~/{}", err, STANDARD_CONFIG_JSON);
-                None
-            },
+pub fn get_tool_package_detail(name: &str, version: &str) -> XResult<ToolPackageDetail> {
+    let registries = registry::get_registries();
+    for registry in &registries {
+        match registry.get_tool_package_detail(name, version) {
+            Ok(tool_package_detail) => return Ok(tool_package_detail),
+            Err(err) => warning!("Get tool {}:{} from registry {} failed: {}", name, version, registry.description(), err),
         }
-    };
-    
-    let mut url = String::with_capacity(1024);
-    match secret {
-        None => {
-            url.push_str(TOOL_PACKAGE_DETAIL_URL_WITHOUT_AUTH);
-            url.push_str("?");
-        },
-        Some(secret) => {
-            url.push_str(TOOL_PACKAGE_DETAIL_URL);
-            url.push_str("?");
-            url.push_str("__auth_token=");
-            url.push_str(&urlencoding::encode(&secret));
-        },
-    };
-    url.push_str("&name=");
-    url.push_str(&urlencoding::encode(name));
-    url.push_str("&ver=");
-    url.push_str(&urlencoding::encode(version));
-    Ok(http::get_url_content(url.as_str())?)
+    }
+    simple_error!("Get tool {}:{} failed, tried {} registries", name, version, registries.len())
 }
 
 pub fn get_and_extract_tool_package(base_dir: &str, dir_with_name: bool, name: &str, version: &str, extract_match: bool) -> XResult<bool> {
     let tool_package_detail = get_tool_package_detail(name, version)?;
-    let build_json_object = json::parse(&tool_package_detail)?;
+    let ToolPackageDetail { url, integrity, name, n, v, .. } = &tool_package_detail;
     if *VERBOSE {
-        debugging!("Get tool {}:{}, result JSON: {}", name, version, json::stringify_pretty(build_json_object.clone(), 4));
+        debugging!("Get tool {}:{}, package: {}, url: {}, integrity: {}", n, v, name, url, integrity);
+    }
+
+    if extract_match &&  version != v {
+        return simple_error!("Required version not match, {}: {} vs {}", name, version, v);
+    }
+    install_tool_package(base_dir, dir_with_name, &tool_package_detail)?;
+    Ok(true)
+}
+
+/// Install a resolved package, e.g. from registry or `build.lock.json`, locked by the package `<n>-<v>`,
+/// another buildj process may have installed the same package while waiting for the lock
+pub fn install_tool_package(base_dir: &str, dir_with_name: bool, tool_package_detail: &ToolPackageDetail) -> XResult<()> {
+    let ToolPackageDetail { n, v, .. } = tool_package_detail;
+    let _lock = InstallLock::acquire(base_dir, &format!("{}-{}", n, v))?;
+    if let Some(installed_dir) = find_installed_package(base_dir, dir_with_name, tool_package_detail) {
+        success!("Package {}:{} is installed: {}", n, v, installed_dir);
+        return Ok(());
     }
-    if build_json_object["status"] != 200 {
-        return simple_error!("Error in get tool package detail: {}", build_json_object["message"]);
+    // download and extract in staging dir, then rename into place, so an interrupted install never looks installed
+    let staging_dir = format!("{}/{}{}-{}", base_dir, STAGING_DIR_PREFIX, n, v);
+    if Path::new(&staging_dir).exists() {
+        warning!("Remove stale staging dir: {}", staging_dir);
+        fs::remove_dir_all(&staging_dir)?;
     }
-    let data = &build_json_object["data"];
-    let integrity = &data["integrity"];
-    let url = &data["url"];
-    let name = &data["name"];
-    if integrity.is_null() || url.is_null() || name.is_null() {
-        return simple_error!("Parse tool package detail failed: {}", tool_package_detail);
+    local_util::init_dir(&staging_dir);
+    let install_result = download_and_extract_tool_package(base_dir, &staging_dir, tool_package_detail)
+        .and_then(|_| move_staging_into_place(base_dir, dir_with_name, &staging_dir, tool_package_detail));
+    if Path::new(&staging_dir).exists() {
+        if let Err(err) = fs::remove_dir_all(&staging_dir) {
+            warning!("Remove staging dir: {} failed: {}", staging_dir, err);
+        }
     }
-    let n = data["n"].to_string();
-    let v = data["v"].to_string();
+    install_result
+}
 
-    if extract_match &&  version != v {
-        return simple_error!("Required version not match, {}: {} vs {}", name, version, &v);
+/// Dir with an install manifest of the same package, `<base_dir>/<n>-<v>` when `dir_with_name`, otherwise any dir in `base_dir`
+fn find_installed_package(base_dir: &str, dir_with_name: bool, tool_package_detail: &ToolPackageDetail) -> Option<String> {
+    let ToolPackageDetail { n, v, integrity, .. } = tool_package_detail;
+    let installed_dirs = iff!(dir_with_name, vec![format!("{}/{}-{}", base_dir, n, v)], local_util::list_sub_dirs(base_dir));
+    installed_dirs.into_iter().find(|installed_dir| match manifest::get_install_state(installed_dir) {
+        InstallState::Installed(install_manifest) => install_manifest.name == *n && install_manifest.version == *v
+            && install_manifest.integrity == *integrity,
+        _ => false,
+    })
+}
+
+/// Package is downloaded to `<base_dir>/.download-<name>`, kept when download fails so next run can resume
+fn download_and_extract_tool_package(base_dir: &str, staging_dir: &str, tool_package_detail: &ToolPackageDetail) -> XResult<()> {
+    let ToolPackageDetail { url, integrity, name, size, .. } = tool_package_detail;
+    let download_file_name = format!("{}/{}{}", base_dir, DOWNLOAD_FILE_PREFIX, name);
+
+    // integrity is verified while downloading, a mismatched download is deleted
+    information!("Start download: {} -> {}", url, &download_file_name);
+    http::download_url(url, &download_file_name, Some(integrity), *size)?;
+    if let Err(err) = signature::verify_package_signature(tool_package_detail, &download_file_name) {
+        fs::remove_file(&download_file_name)?;
+        return Err(err);
     }
 
-    let mut target_base_dir = String::with_capacity(512);
-    target_base_dir.push_str(base_dir);
+    success!("Start extract file: {}", &download_file_name);
+    archive::extract_package_to(&download_file_name, staging_dir)?;
+    fs::remove_file(&download_file_name)?;
+    Ok(())
+}
+
+/// `dir_with_name`: staging dir becomes `<base_dir>/<n>-<v>`, otherwise each extracted dir is moved into `<base_dir>`
+fn move_staging_into_place(base_dir: &str, dir_with_name: bool, staging_dir: &str, tool_package_detail: &ToolPackageDetail) -> XResult<()> {
+    let install_manifest = InstallManifest::new(tool_package_detail);
     if dir_with_name {
-        target_base_dir.push_str("/");
-        target_base_dir.push_str(&format!("{}-{}", n, v));
+        install_manifest.write_to(staging_dir)?;
+        let target_dir = format!("{}/{}-{}", base_dir, tool_package_detail.n, tool_package_detail.v);
+        return rename_into_place(staging_dir, &target_dir);
+    }
+    for entry in fs::read_dir(staging_dir)? {
+        let entry_path = entry?.path();
+        let (entry_path_str, entry_name) = match (entry_path.to_str(), entry_path.file_name().and_then(|n| n.to_str())) {
+            (Some(p), Some(n)) => (p, n),
+            _ => return simple_error!("Invalid extracted path: {:?}", entry_path),
+        };
+        if entry_path.is_dir() {
+            install_manifest.write_to(entry_path_str)?;
+        }
+        rename_into_place(entry_path_str, &format!("{}/{}", base_dir, entry_name))?;
+    }
+    Ok(())
+}
+
+fn rename_into_place(from: &str, to: &str) -> XResult<()> {
+    if fs::symlink_metadata(to).is_ok() {
+        warning!("Replace broken install: {}", to);
+        if Path::new(to).is_dir() {
+            fs::remove_dir_all(to)?;
+        } else {
+            fs::remove_file(to)?;
+        }
+    }
+    if *VERBOSE {
+        debugging!("Rename {} -> {}", from, to);
     }
-    local_util::init_dir(&target_base_dir);
-    let target_file_name = format!("{}/{}", &target_base_dir, name.to_string());
+    fs::rename(from, to)?;
+    success!("Install success: {}", to);
+    Ok(())
+}
 
-    information!("Start download: {} -> {}", &url.to_string(), &target_file_name);
-    http::download_url(&url.to_string(), &mut File::create(&target_file_name)?)?;
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    information!("Start verify integrity: {} ...", &target_file_name);
-    if local_util::verify_file_integrity(&integrity.to_string(), &target_file_name)? {
-        success!("Verify integrity success.");
-    } else {
-        return simple_error!("Verify integrity failed!");
+    #[test]
+    fn test_get_builder_install_state_legacy() {
+        let test_dir = std::env::temp_dir().join(format!("buildj-test-tool-legacy-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&test_dir);
+        let builder_dir = test_dir.join("maven-3.5.2");
+        fs::create_dir_all(builder_dir.join("apache-maven-3.5.2/bin")).unwrap();
+        let builder_dir = builder_dir.to_str().unwrap();
+        assert!(matches!(get_builder_install_state(BuilderName::Maven, builder_dir), InstallState::Broken(_)));
+
+        fs::write(format!("{}/apache-maven-3.5.2/bin/mvn", builder_dir), "#!/bin/sh").unwrap();
+        assert!(matches!(get_builder_install_state(BuilderName::Maven, builder_dir), InstallState::Legacy));
+        // the query never writes the install manifest
+        assert!(!local_util::is_path_exists(builder_dir, manifest::INSTALL_MANIFEST_JSON));
+        // half extracted, `lib` is missing
+        assert!(!adopt_legacy_builder(BuilderName::Maven, "3.5.2", builder_dir));
+        assert!(matches!(get_builder_install_state(BuilderName::Maven, builder_dir), InstallState::Legacy));
+
+        fs::create_dir_all(format!("{}/apache-maven-3.5.2/lib", builder_dir)).unwrap();
+        fs::write(format!("{}/apache-maven-3.5.2/lib/maven-core-3.5.2.jar", builder_dir), "").unwrap();
+        assert!(adopt_legacy_builder(BuilderName::Maven, "3.5.2", builder_dir));
+        match get_builder_install_state(BuilderName::Maven, builder_dir) {
+            InstallState::Installed(install_manifest) => {
+                assert!(install_manifest.is_legacy());
+                assert_eq!((install_manifest.name.as_str(), install_manifest.version.as_str()), ("maven", "3.5.2"));
+            },
+            _ => panic!("legacy builder is not adopted"),
+        }
+        assert!(matches!(get_builder_install_state(BuilderName::Gradle, &format!("{}/gradle-8.0", test_dir.to_str().unwrap())),
+                         InstallState::Missing));
+        fs::remove_dir_all(&test_dir).unwrap();
     }
 
-    success!("Start extract file: {}", &target_file_name);
-    local_util::extract_package_and_wait(&target_base_dir, &name.to_string())?;
+    #[test]
+    fn test_is_maven_dist_complete() {
+        let maven_home = std::env::temp_dir().join(format!("buildj-test-tool-maven-dist-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&maven_home);
+        fs::create_dir_all(maven_home.join("bin")).unwrap();
+        fs::write(maven_home.join("bin/mvn"), "#!/bin/sh").unwrap();
+        assert!(!is_maven_dist_complete(maven_home.to_str().unwrap()));
+        fs::write(maven_home.join("bin/m2.conf"), "main is org.apache.maven.cli.MavenCli from plexus.core").unwrap();
+        fs::create_dir_all(maven_home.join("boot")).unwrap();
+        assert!(!is_maven_dist_complete(maven_home.to_str().unwrap()));
+        fs::create_dir_all(maven_home.join("lib")).unwrap();
+        assert!(is_maven_dist_complete(maven_home.to_str().unwrap()));
+        fs::remove_dir_all(&maven_home).unwrap();
+    }
 
-    Ok(true)
+    #[test]
+    fn test_is_builder_home_complete() {
+        let test_dir = std::env::temp_dir().join(format!("buildj-test-tool-complete-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&test_dir);
+        fs::create_dir_all(test_dir.join("bin")).unwrap();
+        fs::create_dir_all(test_dir.join("lib")).unwrap();
+        let builder_home = test_dir.to_str().unwrap();
+        fs::write(test_dir.join("bin/gradle"), "#!/bin/sh").unwrap();
+        fs::write(test_dir.join("lib/gradle-launcher-8.5.jar"), "").unwrap();
+        assert!(is_builder_home_complete(BuilderName::Gradle, "8.5", builder_home));
+        assert!(!is_builder_home_complete(BuilderName::Gradle, "8.6", builder_home));
+        fs::write(test_dir.join("bin/ant"), "#!/bin/sh").unwrap();
+        fs::write(test_dir.join("lib/ant.jar"), "").unwrap();
+        assert!(!is_builder_home_complete(BuilderName::Ant, "1.10.14", builder_home));
+        fs::write(test_dir.join("lib/ant-launcher.jar"), "").unwrap();
+        assert!(is_builder_home_complete(BuilderName::Ant, "1.10.14", builder_home));
+        fs::remove_dir_all(&test_dir).unwrap();
+    }
 }
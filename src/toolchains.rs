@@ -0,0 +1,280 @@
+//! Maven `toolchains.xml` for maven-toolchains-plugin, lists every JDK from `jdk::list_installed_jdks`:
+//! ```text
+//! <toolchain>
+//!   <type>jdk</type>
+//!   <provides>
+//!     <version>17.0.2</version>
+//!     <vendor>temurin</vendor>
+//!     <id>buildj-temurin-17.0.2</id>
+//!   </provides>
+//!   <configuration>
+//!     <jdkHome>/home/user/.jssp/jdks/jdk-17.0.2+8</jdkHome>
+//!   </configuration>
+//! </toolchain>
+//! ```
+//! Toolchains with id `buildj-*` are managed by buildj, other toolchains, comments and content in an existing file are kept,
+//! the existing file is backed up to `toolchains.xml.bak` and replaced atomically.
+//!
+//! Gradle toolchains get the same JDKs by `-Porg.gradle.java.installations.paths=...`.
+use std::{env, fs};
+use rust_util::XResult;
+
+use crate::{jdk, java_vendor, local_util, infer};
+use crate::jdk_inventory::JdkInfo;
+
+const MAVEN_TOOLCHAINS_XML: &str = ".m2/toolchains.xml";
+/// `<project dir sha256>.xml` is passed by `--global-toolchains` when build.json `toolchains` is true
+const PROJECT_TOOLCHAINS_DIR: &str = ".jssp/toolchains";
+const BUILDJ_TOOLCHAIN_ID_PREFIX: &str = "buildj-";
+const EMPTY_TOOLCHAINS_XML: &str = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<toolchains>\n</toolchains>\n";
+const TOOLCHAINS_START: &str = "<toolchains";
+const TOOLCHAINS_END: &str = "</toolchains>";
+const TOOLCHAIN_START: &str = "<toolchain>";
+const TOOLCHAIN_END: &str = "</toolchain>";
+const COMMENT_START: &str = "<!--";
+const COMMENT_END: &str = "-->";
+pub const GRADLE_INSTALLATIONS_PATHS: &str = "org.gradle.java.installations.paths";
+
+pub fn get_maven_toolchains_file() -> XResult<String> {
+    local_util::get_user_home_dir(MAVEN_TOOLCHAINS_XML)
+}
+
+/// Write buildj JDKs into `toolchains_file`, existing content not managed by buildj is kept,
+/// returns the count of JDKs
+pub fn write_toolchains_file(toolchains_file: &str) -> XResult<usize> {
+    let jdks = jdk::list_installed_jdks();
+    let toolchains_content = match fs::read_to_string(toolchains_file) {
+        Ok(toolchains_content) => Some(toolchains_content),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => None,
+        Err(err) => return simple_error!("Read {} failed: {}", toolchains_file, err),
+    };
+    let toolchains_xml = merge_toolchains(toolchains_content.as_deref().unwrap_or(EMPTY_TOOLCHAINS_XML), &jdks)?;
+    if let Some(toolchains_content) = &toolchains_content {
+        if *toolchains_content == toolchains_xml {
+            return Ok(jdks.len());
+        }
+        fs::write(format!("{}.bak", toolchains_file), toolchains_content)?;
+    }
+    local_util::write_file_atomic(toolchains_file, &toolchains_xml)?;
+    Ok(jdks.len())
+}
+
+/// Write `~/.jssp/toolchains/<project dir sha256>.xml` with buildj JDKs only, for `--global-toolchains`,
+/// one file per project so parallel builds of different projects never share it
+pub fn write_project_toolchains_file(project_dir: &str) -> XResult<String> {
+    let project_dir = fs::canonicalize(project_dir).or_else(|_| env::current_dir())?;
+    let project_dir_sha256 = local_util::calc_sha256(project_dir.to_string_lossy().as_bytes());
+    let project_toolchains_file = local_util::get_user_home_dir(
+        &format!("{}/{}.xml", PROJECT_TOOLCHAINS_DIR, &project_dir_sha256[..16]))?;
+    let toolchains_xml = merge_toolchains(EMPTY_TOOLCHAINS_XML, &jdk::list_installed_jdks())?;
+    local_util::write_file_atomic(&project_toolchains_file, &toolchains_xml)?;
+    Ok(project_toolchains_file)
+}
+
+/// `-Porg.gradle.java.installations.paths=...` listing buildj JDKs, `None` when there is no JDK
+pub fn get_gradle_installations_paths_arg() -> Option<String> {
+    let jdks = jdk::list_installed_jdks();
+    if jdks.is_empty() {
+        return None;
+    }
+    let jdk_homes: Vec<&str> = jdks.iter().map(|jdk_info| jdk_info.home.as_str()).collect();
+    Some(format!("-P{}={}", GRADLE_INSTALLATIONS_PATHS, jdk_homes.join(",")))
+}
+
+/// Install the JDK of the gradle toolchain in `project_dir` by buildj when not installed,
+/// e.g. `JavaLanguageVersion.of(21)`
+pub fn provision_gradle_toolchain(project_dir: &str) {
+    let (java_version, java_vendor) = match infer::read_gradle_toolchain(project_dir) {
+        Some(gradle_toolchain) => gradle_toolchain, None => return,
+    };
+    let java_vendors: Vec<String> = java_vendor.into_iter().collect();
+    match jdk::get_java_home(&java_version, &java_vendors) {
+        Some(java_home) => information!("Gradle toolchain java: {}{}: {}", java_version, jdk::get_vendors_message(&java_vendors), java_home),
+        None => warning!("Provision gradle toolchain java: {}{} failed", java_version, jdk::get_vendors_message(&java_vendors)),
+    }
+}
+
+/// Replace toolchains managed by buildj, or with the same `jdkHome` as a buildj JDK, by `jdks`,
+/// toolchains in XML comments are never touched
+fn merge_toolchains(toolchains_content: &str, jdks: &[JdkInfo]) -> XResult<String> {
+    let jdk_homes: Vec<&str> = jdks.iter().map(|jdk_info| jdk_info.home.as_str()).collect();
+    let toolchains: Vec<String> = jdks.iter().map(to_toolchain).collect();
+    merge_toolchain_blocks(toolchains_content, &toolchains, &jdk_homes)
+}
+
+fn merge_toolchain_blocks(toolchains_content: &str, toolchains: &[String], jdk_homes: &[&str]) -> XResult<String> {
+    // same byte positions as `toolchains_content`, comments are blanked
+    let uncommented_content = blank_xml_comments(toolchains_content);
+    let toolchains_start_pos = match uncommented_content.find(TOOLCHAINS_START) {
+        Some(toolchains_start_pos) => toolchains_start_pos,
+        None => return simple_error!("Cannot find {}> in toolchains.xml", TOOLCHAINS_START),
+    };
+    let toolchains_end_pos = match uncommented_content.rfind(TOOLCHAINS_END) {
+        Some(toolchains_end_pos) if toolchains_end_pos > toolchains_start_pos => toolchains_end_pos,
+        _ => return simple_error!("Cannot find {} in toolchains.xml", TOOLCHAINS_END),
+    };
+    let mut toolchains_xml = String::new();
+    let mut copied_pos = 0;
+    let mut pos = toolchains_start_pos;
+    while let Some(toolchain_start_pos) = uncommented_content[pos..toolchains_end_pos].find(TOOLCHAIN_START).map(|p| p + pos) {
+        let toolchain_end_pos = match uncommented_content[toolchain_start_pos..toolchains_end_pos].find(TOOLCHAIN_END) {
+            Some(toolchain_end_pos) => toolchain_start_pos + toolchain_end_pos + TOOLCHAIN_END.len(),
+            None => return simple_error!("Cannot find {} in toolchains.xml", TOOLCHAIN_END),
+        };
+        let toolchain = &uncommented_content[toolchain_start_pos..toolchain_end_pos];
+        let is_buildj_toolchain = toolchain.contains(&format!("<id>{}", BUILDJ_TOOLCHAIN_ID_PREFIX));
+        let is_same_jdk_home = get_tag_value(toolchain, "jdkHome").map(|h| jdk_homes.contains(&h)).unwrap_or(false);
+        if is_buildj_toolchain || is_same_jdk_home {
+            let (remove_start_pos, remove_end_pos) = get_line_range(toolchains_content, toolchain_start_pos, toolchain_end_pos);
+            toolchains_xml.push_str(&toolchains_content[copied_pos..remove_start_pos]);
+            copied_pos = remove_end_pos;
+        }
+        pos = toolchain_end_pos;
+    }
+    let (insert_pos, _) = get_line_range(toolchains_content, toolchains_end_pos, toolchains_end_pos);
+    toolchains_xml.push_str(&toolchains_content[copied_pos..insert_pos]);
+    if !toolchains.is_empty() && !toolchains_xml.ends_with('\n') {
+        toolchains_xml.push('\n');
+    }
+    for toolchain in toolchains {
+        toolchains_xml.push_str("  ");
+        toolchains_xml.push_str(toolchain);
+        toolchains_xml.push('\n');
+    }
+    toolchains_xml.push_str(&toolchains_content[insert_pos..]);
+    Ok(toolchains_xml)
+}
+
+/// `<!-- ... -->` are replaced by spaces, byte positions are kept
+fn blank_xml_comments(xml_content: &str) -> String {
+    let mut uncommented_content = xml_content.as_bytes().to_vec();
+    let mut pos = 0;
+    while let Some(comment_start_pos) = xml_content[pos..].find(COMMENT_START).map(|p| p + pos) {
+        let comment_end_pos = xml_content[comment_start_pos..].find(COMMENT_END)
+            .map(|p| comment_start_pos + p + COMMENT_END.len()).unwrap_or(xml_content.len());
+        uncommented_content[comment_start_pos..comment_end_pos].fill(b' ');
+        pos = comment_end_pos;
+    }
+    String::from_utf8(uncommented_content).unwrap_or_default()
+}
+
+/// Extend `start..end` to whole lines when only whitespaces are before `start` and after `end` on the lines
+fn get_line_range(content: &str, start: usize, end: usize) -> (usize, usize) {
+    let line_start = content[..start].trim_end_matches([' ', '\t']).len();
+    let rest = content[end..].trim_start_matches([' ', '\t']);
+    let is_line_start = line_start == 0 || content[..line_start].ends_with('\n');
+    match rest.strip_prefix('\n').or_else(|| rest.strip_prefix("\r\n")) {
+        Some(next_line) if is_line_start => (line_start, content.len() - next_line.len()),
+        _ => (start, end),
+    }
+}
+
+fn to_toolchain(jdk_info: &JdkInfo) -> String {
+    let vendor = jdk_info.vendor.as_deref().map(|vendor| java_vendor::identify_vendor(vendor).unwrap_or(vendor));
+    format_toolchain(&jdk_info.version, vendor, &jdk_info.home)
+}
+
+/// Id is `buildj-<vendor>-<version>-<jdk home sha256>`, JDKs of the same vendor and version have different ids
+fn format_toolchain(version: &str, vendor: Option<&str>, jdk_home: &str) -> String {
+    // `17.0.2+8-LTS` -> `17.0.2`, `1.8.0_242-b08` -> `1.8.0_242`
+    let version = version.split(['+', '-']).next().unwrap_or(version);
+    let mut provides = format!("<version>{}</version>", escape_xml(version));
+    if let Some(vendor) = vendor {
+        provides.push_str(&format!("\n      <vendor>{}</vendor>", escape_xml(vendor)));
+    }
+    let jdk_home_sha256 = local_util::calc_sha256(jdk_home.as_bytes());
+    let id = format!("{}{}{}-{}", BUILDJ_TOOLCHAIN_ID_PREFIX, vendor.map(|v| format!("{}-", v)).unwrap_or_default(),
+                     version, &jdk_home_sha256[..8]);
+    provides.push_str(&format!("\n      <id>{}</id>", escape_xml(&id.to_lowercase().replace(|c: char| c.is_whitespace() || c == ',', ""))));
+    format!("<toolchain>
+    <type>jdk</type>
+    <provides>
+      {}
+    </provides>
+    <configuration>
+      <jdkHome>{}</jdkHome>
+    </configuration>
+  </toolchain>", provides, escape_xml(jdk_home))
+}
+
+fn get_tag_value<'a>(xml_content: &'a str, tag: &str) -> Option<&'a str> {
+    let (_, value) = xml_content.split_once(&format!("<{}>", tag))?;
+    let (value, _) = value.split_once(&format!("</{}>", tag))?;
+    Some(value.trim())
+}
+
+fn escape_xml(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const USER_TOOLCHAINS_XML: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!-- my toolchains -->
+<toolchains xmlns="http://maven.apache.org/TOOLCHAINS/1.1.0">
+  <!--
+  <toolchain>
+    <type>jdk</type>
+    <configuration><jdkHome>/opt/old-jdk</jdkHome></configuration>
+  </toolchain>
+  -->
+  <toolchain>
+    <type>jdk</type>
+    <provides><version>11</version></provides>
+    <configuration><jdkHome>/opt/jdk-11</jdkHome></configuration>
+  </toolchain>
+  <toolchain>
+    <type>jdk</type>
+    <provides><id>buildj-temurin-17.0.1</id></provides>
+    <configuration><jdkHome>/home/user/.jssp/jdks/jdk-17.0.1+12</jdkHome></configuration>
+  </toolchain>
+  <toolchain>
+    <type>netbeans</type>
+    <configuration><installDir>/opt/jdk-21</installDir></configuration>
+  </toolchain>
+</toolchains>
+"#;
+
+    #[test]
+    fn test_merge_toolchain_blocks() {
+        let toolchains = vec![format_toolchain("21.0.1+12", Some("temurin"), "/opt/jdk-21")];
+        let toolchains_xml = merge_toolchain_blocks(USER_TOOLCHAINS_XML, &toolchains, &["/opt/jdk-21"]).unwrap();
+        assert!(toolchains_xml.starts_with("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<!-- my toolchains -->\n"));
+        // commented out toolchain is kept as comment
+        assert!(toolchains_xml.contains("  <!--\n  <toolchain>\n    <type>jdk</type>\n    <configuration><jdkHome>/opt/old-jdk</jdkHome>"));
+        assert!(toolchains_xml.contains("<jdkHome>/opt/jdk-11</jdkHome>"));
+        assert!(toolchains_xml.contains("<installDir>/opt/jdk-21</installDir>"));
+        assert!(!toolchains_xml.contains("jdk-17.0.1+12"));
+        assert!(toolchains_xml.ends_with(&format!("  </toolchain>\n  {}\n</toolchains>\n", toolchains[0])));
+        // merging again changes nothing
+        assert_eq!(merge_toolchain_blocks(&toolchains_xml, &toolchains, &["/opt/jdk-21"]).unwrap(), toolchains_xml);
+    }
+
+    #[test]
+    fn test_merge_toolchain_blocks_empty() {
+        let toolchains = vec![format_toolchain("1.8.0_392-b08", None, "/opt/jdk8")];
+        let toolchains_xml = merge_toolchain_blocks(EMPTY_TOOLCHAINS_XML, &toolchains, &["/opt/jdk8"]).unwrap();
+        assert_eq!(toolchains_xml, format!("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<toolchains>\n  {}\n</toolchains>\n", toolchains[0]));
+        assert_eq!(merge_toolchain_blocks("<toolchains></toolchains>", &toolchains, &[]).unwrap(),
+                   format!("<toolchains>\n  {}\n</toolchains>", toolchains[0]));
+        assert!(merge_toolchain_blocks("<!-- <toolchains></toolchains> -->", &toolchains, &[]).is_err());
+    }
+
+    #[test]
+    fn test_format_toolchain_id() {
+        let toolchain_a = format_toolchain("17.0.2+8", Some("temurin"), "/opt/a/jdk-17.0.2+8");
+        let toolchain_b = format_toolchain("17.0.2+8", Some("temurin"), "/opt/b/jdk-17.0.2+8");
+        let id_a = get_tag_value(&toolchain_a, "id").unwrap();
+        assert!(id_a.starts_with("buildj-temurin-17.0.2-"));
+        assert_ne!(id_a, get_tag_value(&toolchain_b, "id").unwrap());
+        assert_eq!(get_tag_value(&toolchain_a, "version"), Some("17.0.2"));
+    }
+
+    #[test]
+    fn test_blank_xml_comments() {
+        assert_eq!(blank_xml_comments("a<!-- b -->c<!-- d"), "a          c      ");
+        assert_eq!(blank_xml_comments("<!-- ü -->x").len(), "<!-- ü -->x".len());
+    }
+}
@@ -0,0 +1,416 @@
+//! Registries that build download and checksum URLs for official distributions directly,
+//! assigned in `registries` by scheme prefix, base URLs can point to a mirror or a local HTTP server:
+//! ```text
+//! maven+https://archive.apache.org/dist/maven      <base>/maven-<major>/<v>/binaries/apache-maven-<v>-bin.tar.gz(.sha512)
+//! gradle+https://services.gradle.org/distributions <base>/gradle-<v>-bin.zip(.sha256)
+//! ant+https://archive.apache.org/dist/ant          <base>/binaries/apache-ant-<v>-bin.tar.gz(.sha512)
+//! sbt+https://github.com/sbt/sbt/releases/download <base>/v<v>/sbt-<v>.tgz(.sha256)
+//! adoptium+https://api.adoptium.net                <base>/v3/assets/... (Eclipse Temurin JDK)
+//! upstream                                         all above with the default base URLs
+//! ```
+//! Versions for `:::list --remote` are read from the directory listing of `<base>/maven-3/`, `<base>/` and
+//! `<base>/binaries/`, and `<base>/v3/info/available_releases` of Adoptium, sbt does not support listing.
+use std::env;
+use json::JsonValue;
+use rust_util::XResult;
+
+use crate::{http, registry::{Registry, ToolPackageDetail}, java_version::JavaVersionReq, misc::VERBOSE};
+
+pub const UPSTREAM: &str = "upstream";
+pub const MAVEN_SCHEME: &str = "maven+";
+pub const GRADLE_SCHEME: &str = "gradle+";
+pub const ANT_SCHEME: &str = "ant+";
+pub const SBT_SCHEME: &str = "sbt+";
+pub const ADOPTIUM_SCHEME: &str = "adoptium+";
+
+const DEFAULT_MAVEN_BASE_URL: &str = "https://archive.apache.org/dist/maven";
+const DEFAULT_GRADLE_BASE_URL: &str = "https://services.gradle.org/distributions";
+const DEFAULT_ANT_BASE_URL: &str = "https://archive.apache.org/dist/ant";
+const DEFAULT_SBT_BASE_URL: &str = "https://github.com/sbt/sbt/releases/download";
+const DEFAULT_ADOPTIUM_BASE_URL: &str = "https://api.adoptium.net";
+/// Maven major versions listed by `:::list --remote`
+const MAVEN_MAJOR_VERSIONS: [&str; 2] = ["3", "4"];
+
+/// Tool names requested by `jdk::get_cloud_java`
+const ADOPTIUM_JDK_NAMES: [&str; 5] = ["jdk-linux", "openjdk-linux", "openjdk-osx", "temurin-linux", "temurin-osx"];
+
+pub fn get_upstream_registries() -> Vec<Box<dyn Registry>> {
+    vec![
+        Box::new(MavenRegistry { base_url: DEFAULT_MAVEN_BASE_URL.to_string() }),
+        Box::new(GradleRegistry { base_url: DEFAULT_GRADLE_BASE_URL.to_string() }),
+        Box::new(AntRegistry { base_url: DEFAULT_ANT_BASE_URL.to_string() }),
+        Box::new(SbtRegistry { base_url: DEFAULT_SBT_BASE_URL.to_string() }),
+        Box::new(AdoptiumRegistry { base_url: DEFAULT_ADOPTIUM_BASE_URL.to_string() }),
+    ]
+}
+
+/// `maven+https://...` -> `MavenRegistry`, etc.
+pub fn get_upstream_registry(url: &str) -> Option<Box<dyn Registry>> {
+    if let Some(base_url) = url.strip_prefix(MAVEN_SCHEME) {
+        Some(Box::new(MavenRegistry { base_url: base_url.trim_end_matches('/').to_string() }))
+    } else if let Some(base_url) = url.strip_prefix(GRADLE_SCHEME) {
+        Some(Box::new(GradleRegistry { base_url: base_url.trim_end_matches('/').to_string() }))
+    } else if let Some(base_url) = url.strip_prefix(ANT_SCHEME) {
+        Some(Box::new(AntRegistry { base_url: base_url.trim_end_matches('/').to_string() }))
+    } else if let Some(base_url) = url.strip_prefix(SBT_SCHEME) {
+        Some(Box::new(SbtRegistry { base_url: base_url.trim_end_matches('/').to_string() }))
+    } else if let Some(base_url) = url.strip_prefix(ADOPTIUM_SCHEME) {
+        Some(Box::new(AdoptiumRegistry { base_url: base_url.trim_end_matches('/').to_string() }))
+    } else {
+        None
+    }
+}
+
+pub struct MavenRegistry {
+    pub base_url: String,
+}
+
+impl Registry for MavenRegistry {
+    fn description(&self) -> String {
+        format!("{}{}", MAVEN_SCHEME, self.base_url)
+    }
+
+    fn get_tool_package_detail(&self, name: &str, version: &str) -> XResult<ToolPackageDetail> {
+        if name != "maven" {
+            return simple_error!("Tool {} is not supported by registry: {}", name, self.description());
+        }
+        let major_version = version.split('.').next().unwrap_or(version);
+        let file_name = format!("apache-maven-{}-bin.tar.gz", version);
+        let url = format!("{}/maven-{}/{}/binaries/{}", self.base_url, major_version, version, file_name);
+        let integrity = get_checksum_integrity("sha512", &format!("{}.sha512", url))?;
+        Ok(ToolPackageDetail { url, integrity, name: file_name, n: name.to_string(), v: version.to_string(), size: None, signature_url: None })
+    }
+
+    fn list_tool_versions(&self, name: &str) -> XResult<Vec<String>> {
+        if name != "maven" {
+            return simple_error!("Tool {} is not supported by registry: {}", name, self.description());
+        }
+        let mut versions = vec![];
+        for major_version in &MAVEN_MAJOR_VERSIONS {
+            let listing_url = format!("{}/maven-{}/", self.base_url, major_version);
+            match http::get_url_content(&listing_url) {
+                // `<a href="3.9.6/">3.9.6/</a>`
+                Ok(listing_content) => versions.extend(get_listing_names(&listing_content).iter()
+                    .filter_map(|name| name.strip_suffix('/'))
+                    .filter(|version| version.starts_with(|c: char| c.is_ascii_digit()))
+                    .map(|version| version.to_string())),
+                Err(err) => if *VERBOSE {
+                    debugging!("List maven versions: {} failed: {}", listing_url, err);
+                },
+            }
+        }
+        Ok(versions)
+    }
+}
+
+pub struct GradleRegistry {
+    pub base_url: String,
+}
+
+impl Registry for GradleRegistry {
+    fn description(&self) -> String {
+        format!("{}{}", GRADLE_SCHEME, self.base_url)
+    }
+
+    fn get_tool_package_detail(&self, name: &str, version: &str) -> XResult<ToolPackageDetail> {
+        if name != "gradle" {
+            return simple_error!("Tool {} is not supported by registry: {}", name, self.description());
+        }
+        let file_name = format!("gradle-{}-bin.zip", version);
+        let url = format!("{}/{}", self.base_url, file_name);
+        let integrity = get_checksum_integrity("sha256", &format!("{}.sha256", url))?;
+        Ok(ToolPackageDetail { url, integrity, name: file_name, n: name.to_string(), v: version.to_string(), size: None, signature_url: None })
+    }
+    fn list_tool_versions(&self, name: &str) -> XResult<Vec<String>> {
+        if name != "gradle" {
+            return simple_error!("Tool {} is not supported by registry: {}", name, self.description());
+        }
+        // `<a href="/distributions/gradle-8.5-bin.zip">`
+        let listing_content = http::get_url_content(&format!("{}/", self.base_url))?;
+        Ok(get_listing_names(&listing_content).iter()
+            .filter_map(|name| name.strip_prefix("gradle-")?.strip_suffix("-bin.zip"))
+            .map(|version| version.to_string())
+            .collect())
+    }
+}
+
+pub struct AntRegistry {
+    pub base_url: String,
+}
+
+impl Registry for AntRegistry {
+    fn description(&self) -> String {
+        format!("{}{}", ANT_SCHEME, self.base_url)
+    }
+
+    fn get_tool_package_detail(&self, name: &str, version: &str) -> XResult<ToolPackageDetail> {
+        if name != "ant" {
+            return simple_error!("Tool {} is not supported by registry: {}", name, self.description());
+        }
+        let file_name = format!("apache-ant-{}-bin.tar.gz", version);
+        let url = format!("{}/binaries/{}", self.base_url, file_name);
+        let integrity = get_checksum_integrity("sha512", &format!("{}.sha512", url))?;
+        Ok(ToolPackageDetail { url, integrity, name: file_name, n: name.to_string(), v: version.to_string(), size: None, signature_url: None })
+    }
+
+    fn list_tool_versions(&self, name: &str) -> XResult<Vec<String>> {
+        if name != "ant" {
+            return simple_error!("Tool {} is not supported by registry: {}", name, self.description());
+        }
+        // `<a href="apache-ant-1.10.14-bin.tar.gz">`
+        let listing_content = http::get_url_content(&format!("{}/binaries/", self.base_url))?;
+        Ok(get_listing_names(&listing_content).iter()
+            .filter_map(|name| name.strip_prefix("apache-ant-")?.strip_suffix("-bin.tar.gz"))
+            .map(|version| version.to_string())
+            .collect())
+    }
+}
+
+pub struct SbtRegistry {
+    pub base_url: String,
+}
+
+impl Registry for SbtRegistry {
+    fn description(&self) -> String {
+        format!("{}{}", SBT_SCHEME, self.base_url)
+    }
+
+    fn get_tool_package_detail(&self, name: &str, version: &str) -> XResult<ToolPackageDetail> {
+        if name != "sbt" {
+            return simple_error!("Tool {} is not supported by registry: {}", name, self.description());
+        }
+        // extracted as `sbt/bin/sbt`
+        let file_name = format!("sbt-{}.tgz", version);
+        let url = format!("{}/v{}/{}", self.base_url, version, file_name);
+        let integrity = get_checksum_integrity("sha256", &format!("{}.sha256", url))?;
+        Ok(ToolPackageDetail { url, integrity, name: file_name, n: name.to_string(), v: version.to_string(), size: None, signature_url: None })
+    }
+}
+
+pub struct AdoptiumRegistry {
+    pub base_url: String,
+}
+
+impl AdoptiumRegistry {
+    fn get_assets_url(&self, version: &str) -> XResult<String> {
+        let (os, arch) = get_adoptium_os_and_arch()?;
+        let query = format!("architecture={}&image_type=jdk&jvm_impl=hotspot&os={}&vendor=eclipse", arch, os);
+        let java_version_req = match JavaVersionReq::parse(version) {
+            Some(java_version_req) => java_version_req,
+            None => return simple_error!("Invalid java version: {}", version),
+        };
+        // 1.8 -> latest 8, >=11 -> [11,)
+        match java_version_req.get_feature_only() {
+            Some(feature) => Ok(format!("{}/v3/assets/latest/{}/hotspot?{}", self.base_url, feature, query)),
+            None => Ok(format!("{}/v3/assets/version/{}?{}&heap_size=normal&page_size=1&project=jdk&release_type=ga&sort_order=DESC",
+                               self.base_url, urlencoding::encode(&java_version_req.to_version_range()), query)),
+        }
+    }
+}
+
+impl Registry for AdoptiumRegistry {
+    fn description(&self) -> String {
+        format!("{}{}", ADOPTIUM_SCHEME, self.base_url)
+    }
+
+    fn get_tool_package_detail(&self, name: &str, version: &str) -> XResult<ToolPackageDetail> {
+        if !ADOPTIUM_JDK_NAMES.contains(&name) {
+            return simple_error!("Tool {} is not supported by registry: {}", name, self.description());
+        }
+        let assets_url = self.get_assets_url(version)?;
+        let assets_content = http::get_url_content(&assets_url)?;
+        if *VERBOSE {
+            debugging!("Get adoptium assets: {}, result: {}", assets_url, assets_content);
+        }
+        let assets_object = json::parse(&assets_content)?;
+        // latest: [{"binary": {...}, "version": {...}}], version: [{"binaries": [{...}], "version_data": {...}}]
+        let asset = &assets_object[0];
+        let (binary, version_object) = if asset["binary"].is_object() {
+            (&asset["binary"], &asset["version"])
+        } else {
+            (&asset["binaries"][0], &asset["version_data"])
+        };
+        parse_adoptium_binary(name, binary, version_object).ok_or_else(|| {
+            rust_util::SimpleError::new(format!("Cannot find JDK {} in registry: {}", version, self.description())).into()
+        })
+    }
+    fn list_tool_versions(&self, name: &str) -> XResult<Vec<String>> {
+        if !ADOPTIUM_JDK_NAMES.contains(&name) {
+            return simple_error!("Tool {} is not supported by registry: {}", name, self.description());
+        }
+        // `{"available_releases": [8, 11, 17, 21], ...}`, feature versions only
+        let releases_content = http::get_url_content(&format!("{}/v3/info/available_releases", self.base_url))?;
+        let releases_object = json::parse(&releases_content)?;
+        Ok(releases_object["available_releases"].members().map(|release| release.to_string()).collect())
+    }
+}
+
+fn parse_adoptium_binary(name: &str, binary: &JsonValue, version_object: &JsonValue) -> Option<ToolPackageDetail> {
+    let package = &binary["package"];
+    Some(ToolPackageDetail {
+        url: package["link"].as_str()?.to_string(),
+        integrity: format!("sha256:hex-{}", package["checksum"].as_str()?),
+        name: package["name"].as_str()?.to_string(),
+        n: name.to_string(),
+        v: version_object["semver"].as_str()?.to_string(),
+        size: package["size"].as_u64(),
+        signature_url: None,
+    })
+}
+
+fn get_adoptium_os_and_arch() -> XResult<(&'static str, &'static str)> {
+    let os = match env::consts::OS {
+        "linux" => "linux",
+        "macos" => "mac",
+        os => return simple_error!("OS is not supported by adoptium: {}", os),
+    };
+    let arch = match env::consts::ARCH {
+        "x86_64" => "x64",
+        "aarch64" => "aarch64",
+        arch => return simple_error!("Arch is not supported by adoptium: {}", arch),
+    };
+    Ok((os, arch))
+}
+
+/// Last path segment of each `href` in an HTML directory listing, keeps the trailing `/` of dirs
+fn get_listing_names(listing_content: &str) -> Vec<String> {
+    let mut names: Vec<String> = vec![];
+    for href in listing_content.split("href=\"").skip(1) {
+        let href = href.split('"').next().unwrap_or("");
+        let is_dir = href.ends_with('/');
+        let name = match href.trim_end_matches('/').rsplit('/').next() {
+            Some(name) if !name.is_empty() => format!("{}{}", name, iff!(is_dir, "/", "")),
+            _ => continue,
+        };
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    names
+}
+
+/// Checksum file is `<hex>` or `<hex>  <file name>`
+fn get_checksum_integrity(alg: &str, checksum_url: &str) -> XResult<String> {
+    let checksum_content = http::get_url_content(checksum_url)?;
+    match checksum_content.split_whitespace().next() {
+        Some(digest_hex) if digest_hex.chars().all(|c| c.is_ascii_hexdigit()) => {
+            Ok(format!("{}:hex-{}", alg, digest_hex.to_lowercase()))
+        },
+        _ => simple_error!("Parse checksum failed, url: {}, content: {}", checksum_url, checksum_content),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::{BufRead, BufReader, Write}, net::{TcpListener, TcpStream}, thread};
+    use super::*;
+
+    /// Stand-in for upstream servers, serves `routes` of path (without query) and body, other paths are 404
+    fn start_http_server(routes: Vec<(&'static str, String)>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                serve_request(stream, &routes);
+            }
+        });
+        base_url
+    }
+
+    fn serve_request(mut stream: TcpStream, routes: &[(&'static str, String)]) {
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line).unwrap();
+        let mut header_line = String::new();
+        while reader.read_line(&mut header_line).unwrap_or(0) > 2 {
+            header_line.clear();
+        }
+        let path = request_line.split_whitespace().nth(1).unwrap_or("/").split('?').next().unwrap_or("/");
+        // a 404 body that looks like a checksum, must not be taken as one
+        let (status, body) = match routes.iter().find(|(route, _)| *route == path) {
+            Some((_, body)) => ("200 OK", body.as_str()),
+            None => ("404 Not Found", "deadbeef"),
+        };
+        let _ = write!(stream, "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", status, body.len(), body);
+    }
+
+    #[test]
+    fn test_maven_registry() {
+        let base_url = start_http_server(vec![
+            ("/maven-3/3.9.6/binaries/apache-maven-3.9.6-bin.tar.gz.sha512", "ABCDEF0123  apache-maven-3.9.6-bin.tar.gz\n".to_string()),
+            ("/maven-3/", r#"<a href="../">../</a> <a href="3.9.5/">3.9.5/</a> <a href="3.9.6/">3.9.6/</a>"#.to_string()),
+        ]);
+        let maven_registry = get_upstream_registry(&format!("{}{}/", MAVEN_SCHEME, base_url)).unwrap();
+        let detail = maven_registry.get_tool_package_detail("maven", "3.9.6").unwrap();
+        assert_eq!(detail.url, format!("{}/maven-3/3.9.6/binaries/apache-maven-3.9.6-bin.tar.gz", base_url));
+        assert_eq!(detail.integrity, "sha512:hex-abcdef0123");
+        assert_eq!((detail.name.as_str(), detail.n.as_str(), detail.v.as_str()), ("apache-maven-3.9.6-bin.tar.gz", "maven", "3.9.6"));
+        assert!(maven_registry.get_tool_package_detail("maven", "3.9.7").is_err());
+        assert!(maven_registry.get_tool_package_detail("gradle", "8.5").is_err());
+        // maven-4 is 404
+        assert_eq!(maven_registry.list_tool_versions("maven").unwrap(), vec!["3.9.5", "3.9.6"]);
+    }
+
+    #[test]
+    fn test_gradle_registry() {
+        let base_url = start_http_server(vec![
+            ("/gradle-8.5-bin.zip.sha256", "0123abcd".to_string()),
+            ("/", r#"<a href="/distributions/gradle-8.5-bin.zip"> <a href="/distributions/gradle-8.5-src.zip">
+                     <a href="/distributions/gradle-8.4-bin.zip">"#.to_string()),
+        ]);
+        let gradle_registry = get_upstream_registry(&format!("{}{}", GRADLE_SCHEME, base_url)).unwrap();
+        let detail = gradle_registry.get_tool_package_detail("gradle", "8.5").unwrap();
+        assert_eq!(detail.url, format!("{}/gradle-8.5-bin.zip", base_url));
+        assert_eq!(detail.integrity, "sha256:hex-0123abcd");
+        assert!(gradle_registry.get_tool_package_detail("gradle", "8.6").is_err());
+        assert_eq!(gradle_registry.list_tool_versions("gradle").unwrap(), vec!["8.5", "8.4"]);
+    }
+
+    #[test]
+    fn test_ant_and_sbt_registry() {
+        let base_url = start_http_server(vec![
+            ("/binaries/apache-ant-1.10.14-bin.tar.gz.sha512", "ab01".to_string()),
+            ("/binaries/", r#"<a href="apache-ant-1.10.14-bin.tar.gz"> <a href="apache-ant-1.10.14-bin.tar.gz.sha512">"#.to_string()),
+            ("/v1.9.7/sbt-1.9.7.tgz.sha256", "cd02  sbt-1.9.7.tgz".to_string()),
+        ]);
+        let ant_registry = get_upstream_registry(&format!("{}{}", ANT_SCHEME, base_url)).unwrap();
+        let detail = ant_registry.get_tool_package_detail("ant", "1.10.14").unwrap();
+        assert_eq!(detail.url, format!("{}/binaries/apache-ant-1.10.14-bin.tar.gz", base_url));
+        assert_eq!(detail.integrity, "sha512:hex-ab01");
+        assert_eq!(ant_registry.list_tool_versions("ant").unwrap(), vec!["1.10.14"]);
+
+        let sbt_registry = get_upstream_registry(&format!("{}{}", SBT_SCHEME, base_url)).unwrap();
+        let detail = sbt_registry.get_tool_package_detail("sbt", "1.9.7").unwrap();
+        assert_eq!(detail.url, format!("{}/v1.9.7/sbt-1.9.7.tgz", base_url));
+        assert_eq!(detail.integrity, "sha256:hex-cd02");
+        assert!(sbt_registry.list_tool_versions("sbt").is_err());
+    }
+
+    #[test]
+    fn test_adoptium_registry() {
+        if get_adoptium_os_and_arch().is_err() {
+            return;
+        }
+        let base_url = start_http_server(vec![
+            ("/v3/assets/latest/17/hotspot", r#"[{"binary": {"package": {"link": "https://example.com/jdk-17.0.9.tar.gz",
+                "checksum": "ef03", "name": "jdk-17.0.9.tar.gz", "size": 10}}, "version": {"semver": "17.0.9+9"}}]"#.to_string()),
+            ("/v3/assets/version/%5B11%2C%29", r#"[{"binaries": [{"package": {"link": "https://example.com/jdk-21.0.1.tar.gz",
+                "checksum": "ef04", "name": "jdk-21.0.1.tar.gz"}}], "version_data": {"semver": "21.0.1+12"}}]"#.to_string()),
+            ("/v3/info/available_releases", r#"{"available_releases": [8, 11, 17, 21]}"#.to_string()),
+        ]);
+        let adoptium_registry = get_upstream_registry(&format!("{}{}", ADOPTIUM_SCHEME, base_url)).unwrap();
+        let detail = adoptium_registry.get_tool_package_detail("temurin-linux", "17").unwrap();
+        assert_eq!(detail.url, "https://example.com/jdk-17.0.9.tar.gz");
+        assert_eq!((detail.integrity.as_str(), detail.v.as_str(), detail.size), ("sha256:hex-ef03", "17.0.9+9", Some(10)));
+        let detail = adoptium_registry.get_tool_package_detail("jdk-linux", ">=11").unwrap();
+        assert_eq!((detail.name.as_str(), detail.v.as_str(), detail.size), ("jdk-21.0.1.tar.gz", "21.0.1+12", None));
+        assert!(adoptium_registry.get_tool_package_detail("zulu-linux", "17").is_err());
+        assert_eq!(adoptium_registry.list_tool_versions("temurin-osx").unwrap(), vec!["8", "11", "17", "21"]);
+    }
+
+    #[test]
+    fn test_get_listing_names() {
+        assert_eq!(get_listing_names(r#"<a href="../">..</a><a href="3.9.6/">3.9.6/</a><a href="/d/gradle-8.5-bin.zip">x</a><a href="3.9.6/">"#),
+                   vec!["../", "3.9.6/", "gradle-8.5-bin.zip"]);
+    }
+}
@@ -0,0 +1,179 @@
+use std::{fs, path::Path};
+use rust_util::XResult;
+
+use crate::build_json::{BuildJson, BuildJsonBuilder};
+use crate::misc::VERBOSE;
+
+pub const BUILDER_WRAPPER: &str = "wrapper";
+
+const MAVEN_WRAPPER_PROPERTIES: &str = ".mvn/wrapper/maven-wrapper.properties";
+const GRADLE_WRAPPER_PROPERTIES: &str = "gradle/wrapper/gradle-wrapper.properties";
+const DISTRIBUTION_URL: &str = "distributionUrl";
+
+/// Replace builder `wrapper` (or missing builder) with the maven or gradle version from
+/// `.mvn/wrapper/maven-wrapper.properties` or `gradle/wrapper/gradle-wrapper.properties`
+pub fn resolve_wrapper_builder(build_json: &mut BuildJson, project_dir: &str) -> XResult<()> {
+    let is_wrapper = match &build_json.builder {
+        None => false,
+        Some(builder) => builder.name == BUILDER_WRAPPER,
+    };
+    if build_json.builder.is_some() && !is_wrapper {
+        return Ok(());
+    }
+    match find_wrapper_builder(project_dir)? {
+        Some(builder) => {
+            success!("Find {} wrapper, version: {}", builder.name, builder.version);
+            build_json.builder = Some(builder);
+            Ok(())
+        },
+        None => iff!(is_wrapper, simple_error!("Cannot find maven or gradle wrapper in: {}", project_dir), Ok(())),
+    }
+}
+
+pub fn find_wrapper_builder(project_dir: &str) -> XResult<Option<BuildJsonBuilder>> {
+    let wrappers = [
+        ("maven", MAVEN_WRAPPER_PROPERTIES, "apache-maven-", &["-bin.zip", "-bin.tar.gz"][..]),
+        ("gradle", GRADLE_WRAPPER_PROPERTIES, "gradle-", &["-bin.zip", "-all.zip"][..]),
+    ];
+    for (name, properties_file, prefix, suffixes) in &wrappers {
+        let wrapper_properties = format!("{}/{}", project_dir, properties_file);
+        if !Path::new(&wrapper_properties).is_file() {
+            continue;
+        }
+        if *VERBOSE {
+            debugging!("Find wrapper properties: {}", wrapper_properties);
+        }
+        let properties_content = fs::read_to_string(&wrapper_properties)?;
+        let distribution_url = match get_property(&properties_content, DISTRIBUTION_URL) {
+            Some(url) => url,
+            None => return simple_error!("Cannot find {} in: {}", DISTRIBUTION_URL, wrapper_properties),
+        };
+        let version = match parse_distribution_version(&distribution_url, prefix, suffixes) {
+            Some(version) => version,
+            None => return simple_error!("Cannot parse version from {}: {}", DISTRIBUTION_URL, distribution_url),
+        };
+        return Ok(Some(BuildJsonBuilder { name: name.to_string(), version }));
+    }
+    Ok(None)
+}
+
+/// e.g. `https://services.gradle.org/distributions/gradle-7.5-bin.zip` -> `7.5`
+fn parse_distribution_version(distribution_url: &str, prefix: &str, suffixes: &[&str]) -> Option<String> {
+    let file_name = distribution_url.rsplit('/').next()?;
+    let file_name = file_name.strip_prefix(prefix)?;
+    suffixes.iter()
+        .find_map(|suffix| file_name.strip_suffix(suffix))
+        .filter(|version| !version.is_empty())
+        .map(|version| version.to_string())
+}
+
+/// Minimal Java properties reader, supports `key=value`, `key: value` and `\` escapes
+pub fn get_property(properties_content: &str, key: &str) -> Option<String> {
+    for line in properties_content.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with('!') {
+            continue;
+        }
+        let split_pos = match find_property_separator(line) {
+            Some(pos) => pos, None => continue,
+        };
+        if unescape_property(line[..split_pos].trim()) == key {
+            return Some(unescape_property(line[split_pos + 1..].trim()));
+        }
+    }
+    None
+}
+
+/// First `=` or `:` not escaped by `\`, e.g. `a\:b=c` -> 4
+fn find_property_separator(line: &str) -> Option<usize> {
+    let mut escaped = false;
+    for (pos, c) in line.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '=' | ':' => return Some(pos),
+            _ => (),
+        }
+    }
+    None
+}
+
+fn unescape_property(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            if let Some(escaped) = chars.next() {
+                result.push(escaped);
+            }
+        } else {
+            result.push(c);
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_property() {
+        let maven_wrapper_properties = "# Licensed to the Apache Software Foundation (ASF)\n\
+            distributionUrl=https://repo.maven.apache.org/maven2/org/apache/maven/apache-maven/3.9.6/apache-maven-3.9.6-bin.zip\n\
+            wrapperUrl=https://repo.maven.apache.org/maven2/org/apache/maven/wrapper/maven-wrapper/3.2.0/maven-wrapper-3.2.0.jar\n";
+        assert_eq!(get_property(maven_wrapper_properties, DISTRIBUTION_URL).as_deref(),
+                   Some("https://repo.maven.apache.org/maven2/org/apache/maven/apache-maven/3.9.6/apache-maven-3.9.6-bin.zip"));
+
+        let gradle_wrapper_properties = "distributionBase=GRADLE_USER_HOME\n\
+            distributionPath=wrapper/dists\n\
+            distributionUrl=https\\://services.gradle.org/distributions/gradle-8.5-bin.zip\n\
+            zipStoreBase=GRADLE_USER_HOME\n";
+        assert_eq!(get_property(gradle_wrapper_properties, DISTRIBUTION_URL).as_deref(),
+                   Some("https://services.gradle.org/distributions/gradle-8.5-bin.zip"));
+        assert_eq!(get_property(gradle_wrapper_properties, "distributionPath").as_deref(), Some("wrapper/dists"));
+        assert_eq!(get_property(gradle_wrapper_properties, "networkTimeout"), None);
+
+        // `:` separator, comments and escaped separators in key
+        assert_eq!(get_property("! comment\n  distributionUrl : https\\://example.com/gradle-8.5-all.zip", DISTRIBUTION_URL).as_deref(),
+                   Some("https://example.com/gradle-8.5-all.zip"));
+        assert_eq!(get_property("#distributionUrl=a\ndistributionUrl=b", DISTRIBUTION_URL).as_deref(), Some("b"));
+        assert_eq!(get_property("a\\:b=c\na=d", "a:b").as_deref(), Some("c"));
+        assert_eq!(get_property("a\\:b=c\na=d", "a").as_deref(), Some("d"));
+    }
+
+    #[test]
+    fn test_parse_distribution_version() {
+        let maven_suffixes = &["-bin.zip", "-bin.tar.gz"][..];
+        let gradle_suffixes = &["-bin.zip", "-all.zip"][..];
+        assert_eq!(parse_distribution_version("https://repo.maven.apache.org/maven2/org/apache/maven/apache-maven/3.9.6/apache-maven-3.9.6-bin.zip",
+                                              "apache-maven-", maven_suffixes).as_deref(), Some("3.9.6"));
+        assert_eq!(parse_distribution_version("https://example.com/apache-maven-3.5.2-bin.tar.gz", "apache-maven-", maven_suffixes).as_deref(),
+                   Some("3.5.2"));
+        assert_eq!(parse_distribution_version("https://services.gradle.org/distributions/gradle-8.5-bin.zip", "gradle-", gradle_suffixes).as_deref(),
+                   Some("8.5"));
+        assert_eq!(parse_distribution_version("https://services.gradle.org/distributions/gradle-7.6.1-all.zip", "gradle-", gradle_suffixes).as_deref(),
+                   Some("7.6.1"));
+        assert_eq!(parse_distribution_version("https://services.gradle.org/distributions/gradle-8.5-rc-1-bin.zip", "gradle-", gradle_suffixes).as_deref(),
+                   Some("8.5-rc-1"));
+        assert_eq!(parse_distribution_version("https://example.com/gradle-8.5-src.zip", "gradle-", gradle_suffixes), None);
+        assert_eq!(parse_distribution_version("https://example.com/gradle--bin.zip", "gradle-", gradle_suffixes), None);
+        assert_eq!(parse_distribution_version("https://example.com/maven-3.9.6-bin.zip", "apache-maven-", maven_suffixes), None);
+    }
+
+    #[test]
+    fn test_find_wrapper_builder() {
+        let project_dir = std::env::temp_dir().join(format!("buildj-test-wrapper-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&project_dir);
+        fs::create_dir_all(project_dir.join("gradle/wrapper")).unwrap();
+        fs::write(project_dir.join(GRADLE_WRAPPER_PROPERTIES),
+                  "distributionUrl=https\\://services.gradle.org/distributions/gradle-8.5-all.zip\n").unwrap();
+        let builder = find_wrapper_builder(project_dir.to_str().unwrap()).unwrap().unwrap();
+        assert_eq!((builder.name.as_str(), builder.version.as_str()), ("gradle", "8.5"));
+
+        fs::write(project_dir.join(GRADLE_WRAPPER_PROPERTIES), "distributionBase=GRADLE_USER_HOME\n").unwrap();
+        assert!(find_wrapper_builder(project_dir.to_str().unwrap()).is_err());
+        fs::remove_dir_all(&project_dir).unwrap();
+        assert!(find_wrapper_builder(project_dir.to_str().unwrap()).unwrap().is_none());
+    }
+}